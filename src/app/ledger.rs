@@ -1,4 +1,4 @@
-use crate::dom::{AccountSummary, LedgerResult, Tx, BookingService, AccountService};
+use crate::dom::{AccountSummary, Accrual, Adjustment, DisputeSummary, ExposureReport, HistoryEntry, HouseSummary, JournalEntry, LedgerResult, LimitViolation, RiskEvent, StatusChange, TrialBalance, Tx, Unlock, BookingService, AccountService, ReportService};
 use std::sync::Arc;
 
 use async_trait::async_trait;
@@ -25,6 +25,15 @@ impl AccountService for Ledger {
     async fn dump_accounts(&self) -> LedgerResult<Vec<AccountSummary>> {
         self.account_repo.lock().await.dump_accounts().await
     }
+    async fn dump_house_accounts(&self) -> LedgerResult<Vec<HouseSummary>> {
+        self.booking_repo.lock().await.dump_house_accounts().await
+    }
+    async fn dump_unlocks(&self) -> LedgerResult<Vec<Unlock>> {
+        self.account_repo.lock().await.dump_unlocks().await
+    }
+    async fn dump_status_changes(&self) -> LedgerResult<Vec<StatusChange>> {
+        self.account_repo.lock().await.dump_status_changes().await
+    }
 }
 
 #[async_trait]
@@ -33,4 +42,40 @@ impl BookingService for Ledger {
         let mut booking_repo = self.booking_repo.lock().await;
         booking_repo.process_tx(tx).await
     }
-}
\ No newline at end of file
+    async fn process_batch(&self, batch: &str, txs: Vec<Tx>) -> LedgerResult<()> {
+        self.booking_repo.lock().await.process_batch(batch, txs).await
+    }
+    async fn dump_history(&self) -> LedgerResult<Vec<HistoryEntry>> {
+        self.booking_repo.lock().await.dump_history().await
+    }
+    async fn dump_adjustments(&self) -> LedgerResult<Vec<Adjustment>> {
+        self.booking_repo.lock().await.dump_adjustments().await
+    }
+    async fn dump_disputes(&self) -> LedgerResult<Vec<DisputeSummary>> {
+        self.booking_repo.lock().await.dump_disputes().await
+    }
+    async fn dump_accruals(&self) -> LedgerResult<Vec<Accrual>> {
+        self.booking_repo.lock().await.dump_accruals().await
+    }
+    async fn dump_violations(&self) -> LedgerResult<Vec<LimitViolation>> {
+        self.booking_repo.lock().await.dump_violations().await
+    }
+    async fn dump_risk_events(&self) -> LedgerResult<Vec<RiskEvent>> {
+        self.booking_repo.lock().await.dump_risk_events().await
+    }
+    async fn dump_journal(&self) -> LedgerResult<Vec<JournalEntry>> {
+        self.booking_repo.lock().await.dump_journal().await
+    }
+    async fn trial_balance(&self) -> LedgerResult<Vec<TrialBalance>> {
+        self.booking_repo.lock().await.trial_balance().await
+    }
+}
+
+#[async_trait]
+impl ReportService for Ledger {
+    async fn exposure_report(&self) -> LedgerResult<ExposureReport> {
+        let accounts = self.account_repo.lock().await.dump_accounts().await?;
+        let disputes = self.booking_repo.lock().await.dump_disputes().await?;
+        ExposureReport::new(&accounts, &disputes)
+    }
+}
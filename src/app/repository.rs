@@ -1,18 +1,59 @@
 use async_trait::async_trait;
-use crate::dom::{AccountSummary, Account, LedgerResult, Tx};
+use crate::dom::{AccountStatus, AccountSummary, Account, Accrual, Adjustment, Amount, Conversion, Currency, DisputeSummary, HistoryEntry, HouseSummary, JournalEntry, LedgerResult, LimitViolation, RiskEvent, StatusChange, TrialBalance, Tx, Unlock};
 
 #[async_trait]
 pub trait AccountRepository: Send + Sync {
+    async fn get_account(&self, client_id: u16) -> LedgerResult<Account>;
     async fn get_or_create_account(&mut self, client_id: u16) -> LedgerResult<Account>;
-    async fn hold(&mut self, client_id: u16, amount: i64) -> LedgerResult<()>;
-    async fn release(&mut self, client_id: u16, amount: i64) -> LedgerResult<()>;
-    async fn deposit(&mut self, client_id: u16, amount: i64) -> LedgerResult<()>;
-    async fn withdraw(&mut self, client_id: u16, amount: i64) -> LedgerResult<()>;
-    async fn withdraw_and_lock(&mut self, client_id: u16, amount: i64) -> LedgerResult<()>;
+    async fn open_account(&mut self, client_id: u16) -> LedgerResult<()>;
+    async fn close_account(&mut self, client_id: u16) -> LedgerResult<()>;
+    async fn hold(&mut self, client_id: u16, currency: &Currency, amount: Amount) -> LedgerResult<()>;
+    async fn release(&mut self, client_id: u16, currency: &Currency, amount: Amount) -> LedgerResult<()>;
+    async fn hold_claim(&mut self, client_id: u16, currency: &Currency, amount: Amount) -> LedgerResult<()>;
+    async fn drop_claim(&mut self, client_id: u16, currency: &Currency, amount: Amount) -> LedgerResult<()>;
+    async fn pay_claim(&mut self, client_id: u16, currency: &Currency, amount: Amount) -> LedgerResult<()>;
+    async fn authorize(&mut self, client_id: u16, currency: &Currency, amount: Amount) -> LedgerResult<()>;
+    async fn capture(&mut self, client_id: u16, currency: &Currency, authorized: Amount, captured: Amount) -> LedgerResult<()>;
+    async fn void(&mut self, client_id: u16, currency: &Currency, amount: Amount) -> LedgerResult<()>;
+    async fn deposit_pending(&mut self, client_id: u16, currency: &Currency, amount: Amount) -> LedgerResult<()>;
+    async fn settle(&mut self, client_id: u16, currency: &Currency, amount: Amount) -> LedgerResult<()>;
+    async fn reject_settlement(&mut self, client_id: u16, currency: &Currency, amount: Amount) -> LedgerResult<()>;
+    async fn deposit(&mut self, client_id: u16, currency: &Currency, amount: Amount) -> LedgerResult<()>;
+    async fn withdraw(&mut self, client_id: u16, currency: &Currency, amount: Amount) -> LedgerResult<()>;
+    async fn debit(&mut self, client_id: u16, currency: &Currency, amount: Amount) -> LedgerResult<()>;
+    async fn withdraw_and_lock(&mut self, client_id: u16, currency: &Currency, amount: Amount) -> LedgerResult<()>;
+    async fn reverse_chargeback(&mut self, client_id: u16, currency: &Currency, amount: Amount, unlock: bool) -> LedgerResult<()>;
+    async fn transfer(&mut self, from_client_id: u16, to_client_id: u16, currency: &Currency, amount: Amount) -> LedgerResult<()>;
+    async fn reverse_transfer(&mut self, from_client_id: u16, to_client_id: u16, currency: &Currency, amount: Amount) -> LedgerResult<()>;
+    async fn reverse_transfer_chargeback(&mut self, from_client_id: u16, to_client_id: u16, currency: &Currency, amount: Amount, unlock: bool) -> LedgerResult<()>;
+    async fn convert(&mut self, client_id: u16, conversion: &Conversion) -> LedgerResult<()>;
+    async fn unlock(&mut self, unlock: Unlock) -> LedgerResult<()>;
+    async fn restrict_account(&mut self, client_id: u16, status: AccountStatus) -> LedgerResult<()>;
+    async fn change_status(&mut self, change: StatusChange) -> LedgerResult<()>;
+    async fn set_credit_limit(&mut self, client_id: u16, currency: &Currency, limit: Amount) -> LedgerResult<()>;
+    async fn charge_fee(&mut self, client_id: u16, currency: &Currency, amount: Amount) -> LedgerResult<()>;
+    async fn pay_interest(&mut self, client_id: u16, currency: &Currency, amount: Amount) -> LedgerResult<()>;
+    // Changes made after `begin_batch` are either kept by `commit_batch` or
+    // undone by `rollback_batch`.
+    async fn begin_batch(&mut self) -> LedgerResult<()>;
+    async fn commit_batch(&mut self) -> LedgerResult<()>;
+    async fn rollback_batch(&mut self) -> LedgerResult<()>;
     async fn dump_accounts(&self) -> LedgerResult<Vec<AccountSummary>>;
+    async fn dump_unlocks(&self) -> LedgerResult<Vec<Unlock>>;
+    async fn dump_status_changes(&self) -> LedgerResult<Vec<StatusChange>>;
 }
 
 #[async_trait]
 pub trait BookingRepository: Send + Sync {
     async fn process_tx(&mut self, tx: Tx) -> LedgerResult<()>;
+    async fn process_batch(&mut self, batch: &str, txs: Vec<Tx>) -> LedgerResult<()>;
+    async fn dump_history(&self) -> LedgerResult<Vec<HistoryEntry>>;
+    async fn dump_adjustments(&self) -> LedgerResult<Vec<Adjustment>>;
+    async fn dump_disputes(&self) -> LedgerResult<Vec<DisputeSummary>>;
+    async fn dump_accruals(&self) -> LedgerResult<Vec<Accrual>>;
+    async fn dump_violations(&self) -> LedgerResult<Vec<LimitViolation>>;
+    async fn dump_risk_events(&self) -> LedgerResult<Vec<RiskEvent>>;
+    async fn dump_journal(&self) -> LedgerResult<Vec<JournalEntry>>;
+    async fn trial_balance(&self) -> LedgerResult<Vec<TrialBalance>>;
+    async fn dump_house_accounts(&self) -> LedgerResult<Vec<HouseSummary>>;
 }
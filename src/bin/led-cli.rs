@@ -1,51 +1,359 @@
-use std::{sync::Arc, env, fs::File, io};
+use std::{sync::Arc, env, fs::{self, File}, io, error::Error, str::FromStr};
 
+use csv::StringRecord;
 use futures::{lock::Mutex};
-use pico_ledger::{app::Ledger, repo::{InMemoryAccountRepository, InMemoryBookingRepository}, dom::{Tx, BookingService, AccountService}};
+use serde::{Serialize, de::DeserializeOwned};
+use pico_ledger::{app::Ledger, repo::{InMemoryAccountRepository, InMemoryBookingRepository}, dom::{Tx, BookingService, AccountService, ReportService, Currencies, CurrencySpec, RateSpec, RateTable, RoundingMode, FeeSpec, FeeSchedule, InterestSpec, InterestTable, LedgerPolicy, AccountSpec, LimitSpec, LimitTable, RiskRule, RiskRules, Reject}};
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args: Vec<String> = env::args().collect();
-    
-    let account_repo = Arc::new(Mutex::new(InMemoryAccountRepository::new()));
-    let booking_repo = Arc::new(Mutex::new(InMemoryBookingRepository::new(
-        account_repo.clone()
-    )));
+const USAGE: &str = "Usage: led-cli [exposure [--format <csv|json>]] [--policy <file>] [--accounts <file>] [--currencies <file>] [--rates <file>] [--rounding <mode>] [--fees <file>] [--interest <file>] [--limits <file>] [--rules <file>] [--house <file>] [--history <file>] [--unlocks <file>] [--status-changes <file>] [--adjustments <file>] [--disputes <file>] [--accruals <file>] [--rejects <file>] [--violations <file>] [--risk-events <file>] [--journal <file>] [--trial-balance <file>] <tx file>";
 
-    let ledger = Arc::new(Ledger::new(account_repo, booking_repo));
+// Format of the report written by a subcommand.
+#[derive(Default, Clone, Copy)]
+enum Format {
+    #[default]
+    Csv,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("unknown format {:?}", s)),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Args {
+    tx_file: String,
+    // The `exposure` subcommand writes the exposure report instead of the
+    // accounts.
+    exposure: bool,
+    format: Format,
+    policy: Option<String>,
+    accounts: Option<String>,
+    currencies: Option<String>,
+    rates: Option<String>,
+    rounding: RoundingMode,
+    fees: Option<String>,
+    interest: Option<String>,
+    limits: Option<String>,
+    rules: Option<String>,
+    house: Option<String>,
+    history: Option<String>,
+    unlocks: Option<String>,
+    status_changes: Option<String>,
+    adjustments: Option<String>,
+    disputes: Option<String>,
+    accruals: Option<String>,
+    rejects: Option<String>,
+    violations: Option<String>,
+    risk_events: Option<String>,
+    journal: Option<String>,
+    trial_balance: Option<String>,
+}
+
+fn parse_args() -> Result<Args, Box<dyn Error>> {
+    let mut args = Args::default();
+    let mut tx_file = None;
+    let mut it = env::args().skip(1).peekable();
+    if it.peek().is_some_and(|a| a == "exposure") {
+        it.next();
+        args.exposure = true;
+    }
 
-    if args.len() != 2 {
-        return Err("Command needs a tx file as a first parameter".into());
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "--format" if args.exposure => args.format = it.next().ok_or(USAGE)?.parse()?,
+            "--policy" => args.policy = Some(it.next().ok_or(USAGE)?),
+            "--accounts" => args.accounts = Some(it.next().ok_or(USAGE)?),
+            "--currencies" => args.currencies = Some(it.next().ok_or(USAGE)?),
+            "--rates" => args.rates = Some(it.next().ok_or(USAGE)?),
+            "--rounding" => args.rounding = it.next().ok_or(USAGE)?.parse()?,
+            "--fees" => args.fees = Some(it.next().ok_or(USAGE)?),
+            "--interest" => args.interest = Some(it.next().ok_or(USAGE)?),
+            "--limits" => args.limits = Some(it.next().ok_or(USAGE)?),
+            "--rules" => args.rules = Some(it.next().ok_or(USAGE)?),
+            "--house" => args.house = Some(it.next().ok_or(USAGE)?),
+            "--history" => args.history = Some(it.next().ok_or(USAGE)?),
+            "--unlocks" => args.unlocks = Some(it.next().ok_or(USAGE)?),
+            "--status-changes" => args.status_changes = Some(it.next().ok_or(USAGE)?),
+            "--adjustments" => args.adjustments = Some(it.next().ok_or(USAGE)?),
+            "--disputes" => args.disputes = Some(it.next().ok_or(USAGE)?),
+            "--accruals" => args.accruals = Some(it.next().ok_or(USAGE)?),
+            "--rejects" => args.rejects = Some(it.next().ok_or(USAGE)?),
+            "--violations" => args.violations = Some(it.next().ok_or(USAGE)?),
+            "--risk-events" => args.risk_events = Some(it.next().ok_or(USAGE)?),
+            "--journal" => args.journal = Some(it.next().ok_or(USAGE)?),
+            "--trial-balance" => args.trial_balance = Some(it.next().ok_or(USAGE)?),
+            _ if tx_file.is_none() && !arg.starts_with("--") => tx_file = Some(arg),
+            _ => return Err(USAGE.into()),
+        }
     }
 
-    let path = args[1].clone();
+    args.tx_file = tx_file.ok_or(USAGE)?;
+    Ok(args)
+}
+
+fn csv_reader(path: &str) -> Result<csv::Reader<File>, Box<dyn Error>> {
     let file = File::open(path)?;
 
-    let mut rdr = csv::ReaderBuilder::new()
+    Ok(csv::ReaderBuilder::new()
         .has_headers(true)
         .flexible(true)
         .trim(csv::Trim::All)
-        .from_reader(file);
+        .from_reader(file))
+}
 
-    for record in rdr.deserialize() {
-        let r: Tx = record?;
-        if let Err(e) = ledger.process_tx(r).await {
-            eprintln!("Error while processing tx_id {} : {}", r.tx_id, e);
-        };
-    }
+// Reads a row of the transactions file. A row that can't be read is
+// rejected with its line and the columns that could still be made out.
+fn read_tx(headers: &StringRecord, record: csv::Result<StringRecord>) -> Result<Tx, Box<Reject>> {
+    let record = record.map_err(|e| {
+        let line = e.position().map(|p| p.line()).unwrap_or_default();
+        Box::new(Reject::unreadable(line, |_| None, e.to_string()))
+    })?;
+
+    record.deserialize(Some(headers)).map_err(|e| {
+        let line = record.position().map(|p| p.line()).unwrap_or_default();
+        let column = |name: &str| headers.iter().position(|h| h == name)
+            .and_then(|i| record.get(i))
+            .filter(|v| !v.is_empty())
+            .map(str::to_string);
+        Box::new(Reject::unreadable(line, column, e.to_string()))
+    })
+}
 
-    let accounts = ledger.dump_accounts().await?;
+// Reads a whole configuration file, any malformed row fails the command.
+fn read_config<T: DeserializeOwned>(path: &str) -> Result<Vec<T>, Box<dyn Error>> {
+    let rows = csv_reader(path)?.deserialize().collect::<Result<Vec<T>, _>>()?;
+
+    Ok(rows)
+}
+
+// Reads the ledger policy, settings missing from the file keep their default.
+fn read_policy(path: &str) -> Result<LedgerPolicy, Box<dyn Error>> {
+    Ok(toml::from_str(&fs::read_to_string(path)?)?)
+}
+
+fn write_csv<T: Serialize>(path: &str, rows: &[T]) -> Result<(), Box<dyn Error>> {
     let mut wtr = csv::WriterBuilder::new()
         .has_headers(true)
-        .double_quote(true)
-        .flexible(true)
-        .from_writer(io::stdout());
+        .from_path(path)?;
+    for r in rows {
+        wtr.serialize(r)?;
+    }
+    wtr.flush()?;
 
-    for a in accounts.iter() {
-        wtr.serialize(a)?;
+    Ok(())
+}
+
+// The totals and the clients of the report are rows of the same table in CSV.
+async fn write_exposure(ledger: &Ledger, format: Format) -> Result<(), Box<dyn Error>> {
+    let report = ledger.exposure_report().await?;
+    match format {
+        Format::Csv => {
+            let mut wtr = csv::Writer::from_writer(io::stdout());
+            for r in report.rows() {
+                wtr.serialize(r)?;
+            }
+            wtr.flush()?;
+        },
+        Format::Json => {
+            serde_json::to_writer_pretty(io::stdout(), &report)?;
+            println!();
+        },
     }
 
-    wtr.flush()?;
     Ok(())
 }
 
+// Batch of a row of the transactions file, an unreadable row has the batch
+// of its `batch` column if that could be read.
+fn row_batch(row: &Result<Tx, Reject>) -> Option<&String> {
+    match row {
+        Ok(tx) => tx.batch.as_ref(),
+        Err(reject) => reject.batch.as_ref(),
+    }
+}
+
+// Every leg of a failed batch is rejected with the error of the leg that failed.
+// A batch with a row that can't be read fails as a whole before any of its
+// legs is applied.
+async fn process_batch(ledger: &Ledger, rows: Vec<Result<Tx, Reject>>, rejects: &mut Vec<Reject>) {
+    let Some(batch) = rows.first().and_then(row_batch).cloned() else {
+        return;
+    };
+
+    if let Some(line) = rows.iter().find_map(|r| r.as_ref().err()).map(|r| r.line.unwrap_or_default()) {
+        let error = format!("batch {} has an unreadable row at line {}", batch, line);
+        eprintln!("Error while processing batch {} : {}", batch, error);
+        rejects.extend(rows.into_iter().map(|row| match row {
+            Ok(tx) => Reject::new(&tx, error.clone()),
+            Err(reject) => reject,
+        }));
+        return;
+    }
+
+    let txs: Vec<Tx> = rows.into_iter().flatten().collect();
+    if let Err(e) = ledger.process_batch(&batch, txs.clone()).await {
+        eprintln!("Error while processing batch {} : {}", batch, e);
+        rejects.extend(txs.iter().map(|tx| Reject::new(tx, e.to_string())));
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let args = parse_args()?;
+
+    let policy = match &args.policy {
+        Some(path) => read_policy(path)?,
+        None => LedgerPolicy::default(),
+    };
+
+    let currencies = match &args.currencies {
+        Some(path) => Currencies::try_from(read_config::<CurrencySpec>(path)?)?,
+        None => Currencies::default(),
+    };
+
+    let mut rates = RateTable::new(args.rounding);
+    if let Some(path) = &args.rates {
+        for spec in read_config::<RateSpec>(path)? {
+            rates.add(spec);
+        }
+    }
+
+    let mut fees = FeeSchedule::new(args.rounding);
+    if let Some(path) = &args.fees {
+        for spec in read_config::<FeeSpec>(path)? {
+            fees.add(spec);
+        }
+    }
+
+    let mut interest = InterestTable::new(args.rounding);
+    if let Some(path) = &args.interest {
+        for spec in read_config::<InterestSpec>(path)? {
+            interest.add(spec);
+        }
+    }
+
+    let limits = match &args.limits {
+        Some(path) => LimitTable::from_specs(read_config::<LimitSpec>(path)?),
+        None => LimitTable::default(),
+    };
+
+    let rules = match &args.rules {
+        Some(path) => RiskRules::from_specs(read_config::<RiskRule>(path)?),
+        None => RiskRules::default(),
+    };
+
+    let account_repo = match &args.accounts {
+        Some(path) => InMemoryAccountRepository::try_from(read_config::<AccountSpec>(path)?)?,
+        None => InMemoryAccountRepository::new(),
+    };
+    let account_repo = Arc::new(Mutex::new(account_repo));
+    let booking_repo = Arc::new(Mutex::new(
+        InMemoryBookingRepository::new(account_repo.clone())
+            .with_currencies(currencies)
+            .with_rates(rates)
+            .with_fees(fees)
+            .with_interest(interest)
+            .with_limits(limits)
+            .with_risk_rules(rules)
+            .with_policy(policy)
+    ));
+
+    let ledger = Arc::new(Ledger::new(account_repo, booking_repo));
+
+    // Consecutive rows with the same batch are collected and processed at once.
+    let mut rejects = Vec::new();
+    let mut batch: Vec<Result<Tx, Reject>> = Vec::new();
+    let mut rdr = csv_reader(&args.tx_file)?;
+    let headers = rdr.headers()?.clone();
+    for record in rdr.records() {
+        let row = read_tx(&headers, record).map_err(|reject| *reject);
+        if batch.first().is_some_and(|b| row_batch(b) != row_batch(&row)) {
+            process_batch(&ledger, std::mem::take(&mut batch), &mut rejects).await;
+        }
+        if row_batch(&row).is_some() {
+            batch.push(row);
+            continue;
+        }
+
+        // A row that can't be read is rejected like an invalid transaction,
+        // the rest of the file is still processed.
+        let r = match row {
+            Ok(r) => r,
+            Err(reject) => {
+                eprintln!("Error while reading line {} : {}", reject.line.unwrap_or_default(), reject.error);
+                rejects.push(reject);
+                continue;
+            },
+        };
+
+        if let Err(e) = ledger.process_tx(r.clone()).await {
+            eprintln!("Error while processing tx_id {} : {}", r.tx_id, e);
+            rejects.push(Reject::new(&r, e.to_string()));
+        };
+    }
+    process_batch(&ledger, batch, &mut rejects).await;
+
+    if args.exposure {
+        write_exposure(&ledger, args.format).await?;
+    } else {
+        let accounts = ledger.dump_accounts().await?;
+        let mut wtr = csv::WriterBuilder::new()
+            .has_headers(true)
+            .double_quote(true)
+            .flexible(true)
+            .from_writer(io::stdout());
+
+        for a in accounts.iter() {
+            wtr.serialize(a)?;
+        }
+
+        wtr.flush()?;
+    }
+
+    if let Some(path) = &args.house {
+        write_csv(path, &ledger.dump_house_accounts().await?)?;
+    }
+    if let Some(path) = &args.history {
+        write_csv(path, &ledger.dump_history().await?)?;
+    }
+    if let Some(path) = &args.unlocks {
+        write_csv(path, &ledger.dump_unlocks().await?)?;
+    }
+    if let Some(path) = &args.status_changes {
+        write_csv(path, &ledger.dump_status_changes().await?)?;
+    }
+    if let Some(path) = &args.adjustments {
+        write_csv(path, &ledger.dump_adjustments().await?)?;
+    }
+    if let Some(path) = &args.disputes {
+        write_csv(path, &ledger.dump_disputes().await?)?;
+    }
+    if let Some(path) = &args.accruals {
+        write_csv(path, &ledger.dump_accruals().await?)?;
+    }
+    if let Some(path) = &args.rejects {
+        write_csv(path, &rejects)?;
+    }
+    if let Some(path) = &args.violations {
+        write_csv(path, &ledger.dump_violations().await?)?;
+    }
+    if let Some(path) = &args.risk_events {
+        write_csv(path, &ledger.dump_risk_events().await?)?;
+    }
+    if let Some(path) = &args.journal {
+        write_csv(path, &ledger.dump_journal().await?)?;
+    }
+    if let Some(path) = &args.trial_balance {
+        write_csv(path, &ledger.trial_balance().await?)?;
+    }
+
+    Ok(())
+}
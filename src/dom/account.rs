@@ -1,55 +1,374 @@
-use crate::dom::Amount;
+use std::{collections::BTreeMap, fmt};
+
+use crate::dom::{Amount, Conversion, Currency, LedgerError, LedgerResult, StatusChange, TxType, Unlock};
 use serde::{Serialize, Deserialize};
 
-#[derive(Clone, Copy, Debug)]
+// Balance of a single currency within an account.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Balance {
+    available: Amount,
+    held: Amount,
+    // Funds reserved by authorizations that are neither captured nor voided.
+    authorized: Amount,
+    // Deposits that are not settled yet, they can't be spent.
+    pending: Amount,
+    // Fees charged so far, already deducted from `available`.
+    fees: Amount,
+    // How far `available` may go below zero.
+    credit_limit: Amount,
+}
+
+impl Balance {
+    pub fn get_available(&self) -> Amount {
+        self.available
+    }
+    pub fn get_held(&self) -> Amount {
+        self.held
+    }
+    pub fn get_authorized(&self) -> Amount {
+        self.authorized
+    }
+    pub fn get_pending(&self) -> Amount {
+        self.pending
+    }
+    pub fn get_fees(&self) -> Amount {
+        self.fees
+    }
+    pub fn get_credit_limit(&self) -> Amount {
+        self.credit_limit
+    }
+    // What can still be spent, including the credit line. Negative when
+    // disputes or fees have taken the account past its limit.
+    pub fn get_headroom(&self) -> LedgerResult<Amount> {
+        self.available + self.credit_limit
+    }
+    // Whether no funds are left, the fees charged so far don't count.
+    pub fn is_empty(&self) -> bool {
+        [self.available, self.held, self.authorized, self.pending].iter().all(|a| *a == Amount::ZERO)
+    }
+    pub fn get_total(&self) -> LedgerResult<Amount> {
+        ((self.available + self.held)? + self.authorized)? + self.pending
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct Account {
     id: u16,
-    available: i64,
-    held: i64,
-    locked: bool,
+    balances: BTreeMap<Currency, Balance>,
+    status: AccountStatus,
+    unlocks: Vec<Unlock>,
+    status_changes: Vec<StatusChange>,
+    // Risk tier the limits of the client are looked up by.
+    tier: String,
 }
 
+// Every balance change is computed in full before it's applied, so an
+// overflowing operation leaves the account untouched.
 impl Account {
     pub fn new(client_id: u16) -> Self {
         Self {
             id: client_id,
-            available: 0,
-            held: 0,
-            locked: false,
+            balances: BTreeMap::new(),
+            status: AccountStatus::Active,
+            unlocks: Vec::new(),
+            status_changes: Vec::new(),
+            tier: String::new(),
         }
     }
     pub fn is_locked(&self) -> bool {
-        self.locked
+        self.status.is_locked()
+    }
+    pub fn get_status(&self) -> AccountStatus {
+        self.status
+    }
+    // Adds a restriction to the ones the account already has.
+    pub fn restrict(&mut self, status: AccountStatus) {
+        self.status = self.status.restrict(status);
+    }
+    pub fn change_status(&mut self, change: StatusChange) {
+        self.status = change.to;
+        self.status_changes.push(change);
+    }
+    // Only an account without any funds can be closed.
+    pub fn close(&mut self) -> LedgerResult<()> {
+        if !self.balances.values().all(Balance::is_empty) {
+            return Err(LedgerError::repository_error("account has a balance"));
+        }
+
+        self.restrict(AccountStatus::Closed);
+        Ok(())
+    }
+    pub fn get_status_changes(&self) -> &[StatusChange] {
+        &self.status_changes
     }
     pub fn get_client_id(&self) -> u16 {
         self.id
     }
-    pub fn get_available(&self) -> i64 {
-        self.available
+    pub fn get_tier(&self) -> &str {
+        &self.tier
+    }
+    pub fn set_tier(&mut self, tier: String) {
+        self.tier = tier;
+    }
+    pub fn get_unlocks(&self) -> &[Unlock] {
+        &self.unlocks
+    }
+    // An account that has been unlocked with a review period doesn't allow
+    // withdrawals until the period is over.
+    pub fn is_under_review(&self, seq: u64) -> bool {
+        self.unlocks.last()
+            .and_then(|u| u.review_until)
+            .is_some_and(|until| seq <= until)
+    }
+    pub fn unlock(&mut self, unlock: Unlock) {
+        self.status = AccountStatus::Active;
+        self.unlocks.push(unlock);
+    }
+    pub fn get_currencies(&self) -> impl Iterator<Item = &Currency> {
+        self.balances.keys()
+    }
+    pub fn get_balance(&self, currency: &Currency) -> Balance {
+        self.balances.get(currency).copied().unwrap_or_default()
+    }
+    pub fn get_available(&self, currency: &Currency) -> Amount {
+        self.get_balance(currency).available
+    }
+    pub fn get_held(&self, currency: &Currency) -> Amount {
+        self.get_balance(currency).held
+    }
+    pub fn get_total(&self, currency: &Currency) -> LedgerResult<Amount> {
+        self.get_balance(currency).get_total()
+    }
+    pub fn get_headroom(&self, currency: &Currency) -> LedgerResult<Amount> {
+        self.get_balance(currency).get_headroom()
+    }
+    // A limit below the current overdraft is allowed, the account just can't
+    // spend anything until it's back within the limit.
+    pub fn set_credit_limit(&mut self, currency: &Currency, limit: Amount) -> LedgerResult<()> {
+        if limit.is_negative() {
+            return Err(LedgerError::repository_error("negative credit limit"));
+        }
+
+        let mut b = self.get_balance(currency);
+        b.credit_limit = limit;
+        self.set_balance(currency, b);
+        Ok(())
+    }
+    pub fn hold(&mut self, currency: &Currency, amount: Amount) -> LedgerResult<()> {
+        let mut b = self.get_balance(currency);
+        b.available = (b.available - amount)?;
+        b.held = (b.held + amount)?;
+        self.set_balance(currency, b);
+        Ok(())
+    }
+    pub fn release(&mut self, currency: &Currency, amount: Amount) -> LedgerResult<()> {
+        let mut b = self.get_balance(currency);
+        b.held = (b.held - amount)?;
+        b.available = (b.available + amount)?;
+        self.set_balance(currency, b);
+        Ok(())
+    }
+    // Claims hold funds the client may get back, such as a disputed
+    // withdrawal, without touching the available funds.
+    pub fn hold_claim(&mut self, currency: &Currency, amount: Amount) -> LedgerResult<()> {
+        let mut b = self.get_balance(currency);
+        b.held = (b.held + amount)?;
+        self.set_balance(currency, b);
+        Ok(())
     }
-    pub fn get_total(&self) -> i64 {
-        self.available + self.held
+    pub fn drop_claim(&mut self, currency: &Currency, amount: Amount) -> LedgerResult<()> {
+        let mut b = self.get_balance(currency);
+        b.held = (b.held - amount)?;
+        self.set_balance(currency, b);
+        Ok(())
     }
-    pub fn hold(&mut self, amount: i64) {
-        self.available -= amount;
-        self.held += amount;
+    pub fn authorize(&mut self, currency: &Currency, amount: Amount) -> LedgerResult<()> {
+        let mut b = self.get_balance(currency);
+        b.available = (b.available - amount)?;
+        b.authorized = (b.authorized + amount)?;
+        self.set_balance(currency, b);
+        Ok(())
     }
-    pub fn release(&mut self, amount: i64) {
-        self.held -= amount;
-        self.available += amount;
+    // Finalizes an authorization for `captured`, whatever is left of the
+    // authorized amount goes back to the available funds.
+    pub fn capture(&mut self, currency: &Currency, authorized: Amount, captured: Amount) -> LedgerResult<()> {
+        let mut b = self.get_balance(currency);
+        b.authorized = (b.authorized - authorized)?;
+        b.available = (b.available + (authorized - captured)?)?;
+        self.set_balance(currency, b);
+        Ok(())
     }
-    pub fn deposit(&mut self, amount: i64) {
-        self.available += amount;
+    pub fn void(&mut self, currency: &Currency, amount: Amount) -> LedgerResult<()> {
+        self.capture(currency, amount, Amount::ZERO)
     }
-    pub fn withdraw(&mut self, amount: i64) {
-        self.available -= amount;
+    pub fn deposit_pending(&mut self, currency: &Currency, amount: Amount) -> LedgerResult<()> {
+        let mut b = self.get_balance(currency);
+        b.pending = (b.pending + amount)?;
+        self.set_balance(currency, b);
+        Ok(())
     }
-    pub fn withdraw_and_lock(&mut self, amount: i64) {
-        self.held -= amount;
-        self.locked = true;
+    pub fn settle(&mut self, currency: &Currency, amount: Amount) -> LedgerResult<()> {
+        let mut b = self.get_balance(currency);
+        b.pending = (b.pending - amount)?;
+        b.available = (b.available + amount)?;
+        self.set_balance(currency, b);
+        Ok(())
+    }
+    pub fn reject_settlement(&mut self, currency: &Currency, amount: Amount) -> LedgerResult<()> {
+        let mut b = self.get_balance(currency);
+        b.pending = (b.pending - amount)?;
+        self.set_balance(currency, b);
+        Ok(())
+    }
+    pub fn deposit(&mut self, currency: &Currency, amount: Amount) -> LedgerResult<()> {
+        let mut b = self.get_balance(currency);
+        b.available = (b.available + amount)?;
+        self.set_balance(currency, b);
+        Ok(())
+    }
+    pub fn withdraw(&mut self, currency: &Currency, amount: Amount) -> LedgerResult<()> {
+        let mut b = self.get_balance(currency);
+        b.available = (b.available - amount)?;
+        self.set_balance(currency, b);
+        Ok(())
+    }
+    pub fn convert(&mut self, conversion: &Conversion) -> LedgerResult<()> {
+        let mut a = self.clone();
+        a.withdraw(&conversion.from, conversion.amount)?;
+        a.deposit(&conversion.to, conversion.converted)?;
+        *self = a;
+        Ok(())
+    }
+    // Fees are charged regardless of the available funds and can make the
+    // balance negative.
+    pub fn charge_fee(&mut self, currency: &Currency, amount: Amount) -> LedgerResult<()> {
+        let mut b = self.get_balance(currency);
+        b.available = (b.available - amount)?;
+        b.fees = (b.fees + amount)?;
+        self.set_balance(currency, b);
+        Ok(())
+    }
+    pub fn withdraw_and_lock(&mut self, currency: &Currency, amount: Amount) -> LedgerResult<()> {
+        let mut b = self.get_balance(currency);
+        b.held = (b.held - amount)?;
+        self.set_balance(currency, b);
+        self.restrict(AccountStatus::ChargebackLocked);
+        Ok(())
+    }
+    // Credits back a charged back amount, optionally lifting the lock the
+    // chargeback put on the account.
+    pub fn reverse_chargeback(&mut self, currency: &Currency, amount: Amount, unlock: bool) -> LedgerResult<()> {
+        let mut b = self.get_balance(currency);
+        b.available = (b.available + amount)?;
+        self.set_balance(currency, b);
+        if unlock && self.status == AccountStatus::ChargebackLocked {
+            self.status = AccountStatus::Active;
+        }
+        Ok(())
+    }
+    // One summary per currency the account has ever used. An account that
+    // never had any balance is reported in the default currency.
+    pub fn summaries(&self) -> LedgerResult<Vec<AccountSummary>> {
+        if self.balances.is_empty() {
+            return Ok(vec![AccountSummary::new(self, &Currency::default(), &Balance::default())?]);
+        }
+
+        self.balances.iter()
+            .map(|(currency, balance)| AccountSummary::new(self, currency, balance))
+            .collect()
+    }
+    fn set_balance(&mut self, currency: &Currency, balance: Balance) {
+        self.balances.insert(currency.clone(), balance);
+    }
+}
+
+// Status of an account, deciding which transactions it accepts.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountStatus {
+    #[default]
+    Active,
+    // Accepts deposits but nothing leaves the account.
+    WithdrawalsFrozen,
+    // Nothing new comes into the account, the funds can still leave it.
+    DepositsFrozen,
+    FullyFrozen,
+    Closed,
+    // Locked by a chargeback, only the follow ups of the chargeback are
+    // accepted until the account is unlocked.
+    ChargebackLocked,
+}
+
+impl AccountStatus {
+    // Statuses reported as locked in the `locked` column, they refuse
+    // anything but the exceptions of their own.
+    pub fn is_locked(&self) -> bool {
+        matches!(self, AccountStatus::FullyFrozen | AccountStatus::Closed | AccountStatus::ChargebackLocked)
+    }
+    // Whether the account accepts a transaction of the given type from its
+    // client. Incoming transfers count as deposits of the receiver. Voiding
+    // and settling reserved funds is allowed on any account.
+    pub fn allows(&self, tx_type: TxType) -> bool {
+        match self {
+            _ if tx_type.concludes_reserved() => true,
+            AccountStatus::Active => true,
+            AccountStatus::WithdrawalsFrozen => !matches!(tx_type,
+                TxType::Withdrawal | TxType::Transfer | TxType::Authorize | TxType::Capture),
+            AccountStatus::DepositsFrozen => !matches!(tx_type,
+                TxType::Deposit | TxType::AdjustmentCredit | TxType::Accrue),
+            AccountStatus::FullyFrozen | AccountStatus::Closed => false,
+            AccountStatus::ChargebackLocked => tx_type.follows_chargeback(),
+        }
+    }
+    // The stricter of two statuses, frozen withdrawals and frozen deposits
+    // together freeze the account fully.
+    pub fn restrict(self, other: AccountStatus) -> AccountStatus {
+        use AccountStatus::*;
+        match (self, other) {
+            (Closed, _) | (_, Closed) => Closed,
+            (ChargebackLocked, _) | (_, ChargebackLocked) => ChargebackLocked,
+            (FullyFrozen, _) | (_, FullyFrozen) => FullyFrozen,
+            (WithdrawalsFrozen, DepositsFrozen) | (DepositsFrozen, WithdrawalsFrozen) => FullyFrozen,
+            (Active, s) | (s, Active) => s,
+            (s, _) => s,
+        }
+    }
+}
+
+impl fmt::Display for AccountStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccountStatus::Active => write!(f, "active"),
+            AccountStatus::WithdrawalsFrozen => write!(f, "withdrawals_frozen"),
+            AccountStatus::DepositsFrozen => write!(f, "deposits_frozen"),
+            AccountStatus::FullyFrozen => write!(f, "fully_frozen"),
+            AccountStatus::Closed => write!(f, "closed"),
+            AccountStatus::ChargebackLocked => write!(f, "chargeback_locked"),
+        }
     }
 }
 
+// Accounts owned by the ledger operator rather than by a client.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum HouseAccount {
+    // Receives whatever is lost or gained by rounding currency conversions.
+    FxResidue,
+    // Receives the fees charged to clients.
+    FeeIncome,
+    // Pays the interest accrued on client balances.
+    InterestExpense,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct HouseSummary {
+    pub account: HouseAccount,
+    pub currency: Currency,
+    pub balance: Amount,
+}
+
+// The original `client,available,held,total,locked` columns come first, the
+// later ones are appended after them.
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct AccountSummary {
     pub client: u16,
@@ -57,32 +376,78 @@ pub struct AccountSummary {
     pub held: Amount,
     pub total: Amount,
     pub locked: bool,
+    pub currency: Currency,
+    pub authorized: Amount,
+    pub pending: Amount,
+    pub fees: Amount,
+    pub credit_limit: Amount,
+    pub headroom: Amount,
+    pub status: AccountStatus,
+}
+
+// Account settings read from a configuration file, such as the credit line
+// of a client in a currency and the risk tier of the client.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccountSpec {
+    pub client: u16,
+    #[serde(default)]
+    pub currency: Currency,
+    #[serde(default)]
+    pub credit_limit: Amount,
+    #[serde(default)]
+    pub tier: Option<String>,
 }
 
-impl From<&Account> for AccountSummary {
-    fn from(a: &Account) -> Self {
-        AccountSummary{
+impl AccountSummary {
+    fn new(a: &Account, currency: &Currency, b: &Balance) -> LedgerResult<Self> {
+        Ok(AccountSummary{
             client: a.id,
-            available: a.available.into(),
-            held: a.held.into(),
-            total: (a.available + a.held).into(),
-            locked: a.locked,
-        } 
+            currency: currency.clone(),
+            available: b.available,
+            held: b.held,
+            authorized: b.authorized,
+            pending: b.pending,
+            total: b.get_total()?,
+            locked: a.is_locked(),
+            fees: b.fees,
+            credit_limit: b.credit_limit,
+            headroom: b.get_headroom()?,
+            status: a.status,
+        })
     }
 }
 
 
 #[cfg(test)]
 mod tests {
-    use crate::dom::AccountSummary;
+    use crate::dom::{AccountStatus, AccountSummary, Currency, TxType};
+
+    #[test]
+    fn status_restrictions() {
+        use AccountStatus::*;
+        assert_eq!(FullyFrozen, WithdrawalsFrozen.restrict(DepositsFrozen));
+        assert_eq!(WithdrawalsFrozen, Active.restrict(WithdrawalsFrozen));
+        assert_eq!(ChargebackLocked, ChargebackLocked.restrict(WithdrawalsFrozen));
+        assert_eq!(Closed, FullyFrozen.restrict(Closed));
+
+        assert!(WithdrawalsFrozen.allows(TxType::Deposit));
+        assert!(!WithdrawalsFrozen.allows(TxType::Withdrawal));
+        assert!(DepositsFrozen.allows(TxType::Withdrawal));
+        assert!(!DepositsFrozen.allows(TxType::Deposit));
+        assert!(ChargebackLocked.allows(TxType::ChargebackReversal));
+        assert!(!ChargebackLocked.allows(TxType::Deposit));
+        assert!(!Closed.allows(TxType::Deposit));
+        assert!(ChargebackLocked.allows(TxType::Void) && FullyFrozen.allows(TxType::Settle));
+        assert!(!WithdrawalsFrozen.is_locked() && FullyFrozen.is_locked());
+    }
 
     #[test]
     #[ignore]
     fn serialize_tx_csv() {
         let cases: Vec<(&str, AccountSummary)> = vec![
-            ("client,available,held,total,locked
-1,  1.1,   1.0,    2.1, false
-", AccountSummary{client: 1, available: 1_1000.into(), held: 1_0000.into(), total: 2_1000.into(), locked: false}),
+            ("client,available,held,total,locked,currency,authorized,pending,fees,credit_limit,headroom,status
+1,  1.1,   1.0,    2.1, false,, 0,    0, 0, 0, 1.1, active
+", AccountSummary{client: 1, currency: Currency::default(), available: 1_1000.into(), held: 1_0000.into(), authorized: 0.into(), pending: 0.into(), total: 2_1000.into(), locked: false, fees: 0.into(), credit_limit: 0.into(), headroom: 1_1000.into(), status: AccountStatus::Active}),
         ];
 
         for (expected, case) in cases.iter() {
@@ -0,0 +1,42 @@
+use serde::{Serialize, Deserialize};
+
+use crate::dom::AccountStatus;
+
+// Reason an operator gives for an administrative action.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReasonCode {
+    ChargebackReviewed,
+    FraudCleared,
+    CustomerRequest,
+    OperationalError,
+}
+
+// Record of an account being unlocked by an operator. `seq` is the sequence
+// number of the unlock transaction, the account is under review up to and
+// including `review_until` and doesn't allow withdrawals until then.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Unlock {
+    pub client: u16,
+    pub operator: String,
+    pub reason: ReasonCode,
+    pub seq: u64,
+    pub review_until: Option<u64>,
+    pub timestamp: Option<u64>,
+    pub reference: Option<String>,
+    pub memo: Option<String>,
+}
+
+// Record of an operator moving an account to another status.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StatusChange {
+    pub client: u16,
+    pub operator: String,
+    pub reason: ReasonCode,
+    pub seq: u64,
+    pub from: AccountStatus,
+    pub to: AccountStatus,
+    pub timestamp: Option<u64>,
+    pub reference: Option<String>,
+    pub memo: Option<String>,
+}
@@ -1,17 +1,93 @@
 use serde::Serializer;
 use serde::Deserializer;
 use std::fmt;
+use std::ops::{Add, Neg, Sub};
+use std::str::FromStr;
 use serde::{de, Serialize, Deserialize};
 
+use super::{LedgerError, LedgerResult};
+
+/// Number of fractional digits kept by `Amount`.
+pub const SCALE: usize = 4;
 const PRECISION: i64 = 10000;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
 pub struct Amount(i64);
 
 impl Amount {
+    pub const ZERO: Amount = Amount(0);
+
     pub fn to_i64(&self) -> i64 {
         self.0
     }
+    pub fn is_negative(&self) -> bool {
+        self.0 < 0
+    }
+    // Checks that the amount has no more than `scale` significant decimal places.
+    pub fn fits_scale(&self, scale: usize) -> bool {
+        scale >= SCALE || self.0 % 10_i64.pow((SCALE - scale) as u32) == 0
+    }
+    // Parses an amount dropping the decimal places beyond `SCALE`, which
+    // rounds it towards zero.
+    pub fn parse_truncated(s: &str) -> LedgerResult<Amount> {
+        let s = s.trim();
+        match s.split_once('.') {
+            Some((int_part, frac_part)) if frac_part.len() > SCALE && frac_part.bytes().all(|b| b.is_ascii_digit()) =>
+                format!("{}.{}", int_part, &frac_part[..SCALE]).parse(),
+            _ => s.parse(),
+        }
+    }
+    // Rounds the amount to `scale` decimal places.
+    pub fn round(&self, scale: usize, mode: RoundingMode) -> LedgerResult<Amount> {
+        if scale >= SCALE {
+            return Ok(*self);
+        }
+
+        let step = 10_i128.pow((SCALE - scale) as u32);
+        Amount::try_from(div_round(self.0 as i128, step, mode) * step)
+    }
+}
+
+impl TryFrom<i128> for Amount {
+    type Error = LedgerError;
+
+    fn try_from(value: i128) -> LedgerResult<Self> {
+        i64::try_from(value)
+            .map(Amount)
+            .map_err(|_| LedgerError::overflow(format!("{} units", value)))
+    }
+}
+
+// Arithmetic on amounts is always checked, an overflow is reported as an error
+// instead of wrapping the balance around.
+impl Add for Amount {
+    type Output = LedgerResult<Amount>;
+
+    fn add(self, rhs: Amount) -> Self::Output {
+        self.0.checked_add(rhs.0)
+            .map(Amount)
+            .ok_or_else(|| LedgerError::overflow(format!("{} + {}", self, rhs)))
+    }
+}
+
+impl Sub for Amount {
+    type Output = LedgerResult<Amount>;
+
+    fn sub(self, rhs: Amount) -> Self::Output {
+        self.0.checked_sub(rhs.0)
+            .map(Amount)
+            .ok_or_else(|| LedgerError::overflow(format!("{} - {}", self, rhs)))
+    }
+}
+
+impl Neg for Amount {
+    type Output = LedgerResult<Amount>;
+
+    fn neg(self) -> Self::Output {
+        self.0.checked_neg()
+            .map(Amount)
+            .ok_or_else(|| LedgerError::overflow(format!("-({})", self)))
+    }
 }
 
 impl From<i64> for Amount {
@@ -20,12 +96,117 @@ impl From<i64> for Amount {
     }
 }
 
+// Amounts are parsed digit by digit so that no precision is lost on the way,
+// anything that can't be represented exactly is an error.
+impl FromStr for Amount {
+    type Err = LedgerError;
+
+    fn from_str(s: &str) -> LedgerResult<Self> {
+        let value = parse_decimal(s, SCALE)?;
+        i64::try_from(value).map(Amount).map_err(|_| LedgerError::overflow(s.trim()))
+    }
+}
+
+// Parses a decimal string into an integer number of `10^-scale` units.
+pub(crate) fn parse_decimal(s: &str, scale: usize) -> LedgerResult<i128> {
+    let s = s.trim();
+    let (negative, unsigned) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let (int_part, frac_part) = match unsigned.split_once('.') {
+        Some((i, f)) => (i, Some(f)),
+        None => (unsigned, None),
+    };
+
+    let is_digits = |p: &str| !p.is_empty() && p.bytes().all(|b| b.is_ascii_digit());
+    if !is_digits(int_part) || frac_part.is_some_and(|f| !is_digits(f)) {
+        return Err(LedgerError::malformed_amount(s));
+    }
+
+    // Trailing zeros don't carry any precision and can be dropped.
+    let frac_part = frac_part.unwrap_or("").trim_end_matches('0');
+    if frac_part.len() > scale {
+        return Err(LedgerError::excess_precision(s));
+    }
+
+    let overflow = || LedgerError::overflow(s);
+    let mut value: i128 = 0;
+    for b in int_part.bytes().chain(frac_part.bytes()) {
+        value = value.checked_mul(10)
+            .and_then(|v| v.checked_add((b - b'0') as i128))
+            .ok_or_else(overflow)?;
+    }
+    value = 10_i128.checked_pow((scale - frac_part.len()) as u32)
+        .and_then(|m| value.checked_mul(m))
+        .ok_or_else(overflow)?;
+
+    Ok(if negative { -value } else { value })
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RoundingMode {
+    // Towards zero.
+    #[default]
+    Down,
+    // Away from zero.
+    Up,
+    // To the nearest, ties away from zero.
+    HalfUp,
+    // To the nearest, ties to the even neighbour.
+    HalfEven,
+}
+
+impl FromStr for RoundingMode {
+    type Err = LedgerError;
+
+    fn from_str(s: &str) -> LedgerResult<Self> {
+        match s {
+            "down" => Ok(RoundingMode::Down),
+            "up" => Ok(RoundingMode::Up),
+            "half_up" => Ok(RoundingMode::HalfUp),
+            "half_even" => Ok(RoundingMode::HalfEven),
+            _ => Err(LedgerError::doesnt_exist(format!("rounding mode {:?}", s))),
+        }
+    }
+}
+
+// Divides `n` by a positive `d` rounding the quotient with the given mode.
+pub(crate) fn div_round(n: i128, d: i128, mode: RoundingMode) -> i128 {
+    let q = n / d;
+    let r = (n % d).abs();
+    if r == 0 {
+        return q;
+    }
+
+    let away = if n < 0 { q - 1 } else { q + 1 };
+    let half = (2 * r).cmp(&d);
+    match mode {
+        RoundingMode::Down => q,
+        RoundingMode::Up => away,
+        RoundingMode::HalfUp if half.is_lt() => q,
+        RoundingMode::HalfUp => away,
+        RoundingMode::HalfEven if half.is_lt() || (half.is_eq() && q % 2 == 0) => q,
+        RoundingMode::HalfEven => away,
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let abs = self.0.unsigned_abs();
+        let precision = PRECISION as u64;
+        write!(f, "{}{}.{:0width$}", sign, abs / precision, abs % precision, width = SCALE)
+    }
+}
+
 impl Serialize for Amount {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        serializer.serialize_f64((self.0 as f64) / PRECISION as f64)
+        serializer.collect_str(self)
     }
 }
 
@@ -35,17 +216,14 @@ impl<'de> de::Visitor<'de> for AmountVisitor {
     type Value = Amount;
 
     fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        formatter.write_str("an integer between -2^31 and 2^31")
+        write!(formatter, "a decimal number with at most {} fractional digits", SCALE)
     }
 
-    fn visit_f64<E>(self, value: f64) -> Result<Self::Value, E>
+    fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
     where
         E: de::Error,
     {
-        // When deserializing f64 it's multiplied by required precision points
-        // and only integer part is used without any rounding.
-        // TODO: error checks.
-        Ok(Amount((value * PRECISION as f64) as i64))
+        value.parse().map_err(E::custom)
     }
 }
 
@@ -54,13 +232,14 @@ impl<'de> Deserialize<'de> for Amount {
     where
         D: Deserializer<'de>,
     {
-        deserializer.deserialize_f64(AmountVisitor)
+        deserializer.deserialize_str(AmountVisitor)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::dom::Amount;
+    use std::mem::discriminant;
+    use crate::dom::{Amount, LedgerErrorKind, RoundingMode};
 
     #[test]
     fn deserialize_amount_csv() {
@@ -69,7 +248,10 @@ mod tests {
             ("1.2345", 12345),
             ("1.00", 10000),
             ("1", 10000),
-            ("12345.12345678", 12345_1234),
+            ("0.0003", 3),
+            ("-0.0003", -3),
+            ("12345.12340000", 123_451_234),
+            ("922337203685477.5807", i64::MAX),
         ];
 
         for (c, e) in cases.iter() {
@@ -78,19 +260,83 @@ mod tests {
                 .from_reader(c.as_bytes());
             for record in rdr.deserialize() {
                 let r: Amount = record.unwrap();
-                assert_eq!(*e, r.0);
+                assert_eq!(*e, r.0, "{}", c);
             }
         }
     }
 
+    #[test]
+    fn parse_amount_errors() {
+        let cases: Vec<(&str, LedgerErrorKind)> = vec![
+            ("12345.12345678", LedgerErrorKind::ExcessPrecision(String::new())),
+            ("0.00001", LedgerErrorKind::ExcessPrecision(String::new())),
+            ("922337203685477.5808", LedgerErrorKind::Overflow(String::new())),
+            ("99999999999999999999999999999999999999999", LedgerErrorKind::Overflow(String::new())),
+            ("", LedgerErrorKind::MalformedAmount(String::new())),
+            ("1.", LedgerErrorKind::MalformedAmount(String::new())),
+            (".5", LedgerErrorKind::MalformedAmount(String::new())),
+            ("1e3", LedgerErrorKind::MalformedAmount(String::new())),
+            ("--1", LedgerErrorKind::MalformedAmount(String::new())),
+        ];
+
+        for (c, expected) in cases.iter() {
+            let err = c.parse::<Amount>().unwrap_err();
+            assert_eq!(discriminant(expected), discriminant(err.kind()), "{}: {}", c, err);
+        }
+    }
+
+    #[test]
+    fn checked_arithmetic() {
+        let max = Amount::from(i64::MAX);
+        let min = Amount::from(i64::MIN);
+
+        assert_eq!(Amount::from(3_0000), (Amount::from(1_0000) + Amount::from(2_0000)).unwrap());
+        assert_eq!(Amount::from(-1_0000), (Amount::from(1_0000) - Amount::from(2_0000)).unwrap());
+        assert_eq!(Amount::from(-1_0000), (-Amount::from(1_0000)).unwrap());
+        assert_eq!(min, ((-max).unwrap() - Amount::from(1)).unwrap());
+
+        for res in [max + Amount::from(1), min - Amount::from(1), -min] {
+            let err = res.unwrap_err();
+            assert!(matches!(err.kind(), LedgerErrorKind::Overflow(_)), "{}", err);
+        }
+    }
+
+    #[test]
+    fn round_amount() {
+        use crate::dom::RoundingMode::*;
+        let cases: Vec<(i64, usize, RoundingMode, i64)> = vec![
+            (1_2345, 2, Down, 1_2300),
+            (1_2345, 2, Up, 1_2400),
+            (1_2350, 2, HalfUp, 1_2400),
+            (1_2349, 2, HalfUp, 1_2300),
+            (1_2350, 2, HalfEven, 1_2400),
+            (1_2250, 2, HalfEven, 1_2200),
+            (1_2251, 2, HalfEven, 1_2300),
+            (-1_2345, 2, Down, -1_2300),
+            (-1_2345, 2, Up, -1_2400),
+            (-1_2350, 2, HalfUp, -1_2400),
+            (-1_2250, 2, HalfEven, -1_2200),
+            (1_5000, 0, HalfEven, 2_0000),
+            (1_2345, 4, Up, 1_2345),
+        ];
+
+        for (value, scale, mode, expected) in cases {
+            let r = Amount::from(value).round(scale, mode).unwrap();
+            assert_eq!(Amount::from(expected), r, "{} {} {:?}", value, scale, mode);
+        }
+        assert!(Amount::from(i64::MAX).round(0, Up).is_err());
+    }
+
     #[test]
     fn serialize_amount_csv() {
         let cases: Vec<(&str, Amount)> = vec![
-            ("1.2\n", Amount::from(1_2000)),
+            ("1.2000\n", Amount::from(1_2000)),
             ("1.2345\n", Amount::from(1_2345)),
-            ("1.0\n", Amount::from(1_0000)),
-            ("1234.0\n", Amount::from(1234_0000)),
+            ("1.0000\n", Amount::from(1_0000)),
+            ("1234.0000\n", Amount::from(1234_0000)),
             ("0.1234\n", Amount::from(1234)),
+            ("-0.0003\n", Amount::from(-3)),
+            ("-922337203685477.5808\n", Amount::from(i64::MIN)),
         ];
 
         for (e, c) in cases.iter() {
@@ -104,4 +350,4 @@ mod tests {
             assert_eq!(e.to_string(), data);
         }
     }
-}
\ No newline at end of file
+}
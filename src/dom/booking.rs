@@ -1,34 +1,131 @@
+use serde::{Serialize, Deserialize};
 
-#[derive(Clone, Copy, PartialEq)]
+use crate::dom::{Amount, BookingKind, Currency, LedgerError, LedgerResult, TxMetadata, TxType};
+
+
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum BookingState {
     Pristine,
     Normal,
-    Disputed,
+    // Authorization waiting for a capture or a void.
+    Authorized,
+    Voided,
+    Expired,
+    // Deposit waiting for a settlement.
+    Pending,
+    Rejected,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum DisputeState {
+    Open,
     Resolved,
     Chargeback,
+    // The merchant contested the chargeback.
+    Represented,
+    // The chargeback has been reversed and the funds credited back.
+    Reversed,
+}
+
+// Dispute of a part or all of a booking. Disputes are identified by an id
+// unique within the booking, a dispute without an id has the id 0.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dispute {
+    id: u32,
+    amount: Amount,
+    state: DisputeState,
+}
+
+impl Dispute {
+    pub fn get_id(&self) -> u32 {
+        self.id
+    }
+    pub fn get_amount(&self) -> Amount {
+        self.amount
+    }
+    pub fn get_state(&self) -> DisputeState {
+        self.state
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct DisputeSummary {
+    pub tx: u32,
+    pub client: u16,
+    pub dispute: u32,
+    pub amount: Amount,
+    pub currency: Currency,
+    pub state: DisputeState,
+    // Client whose funds a chargeback takes, the receiver of a transfer.
+    pub holder: u16,
+    pub tx_type: TxType,
 }
 
 // Booking represents the state of a transaction.
-// A transaction that has been charged back or resolved gets locked.
-#[derive(Clone, Copy)]
+// Several disputes can run against the same booking as long as the disputed
+// and charged back amounts together don't exceed the amount of the booking.
+#[derive(Clone)]
 pub struct Booking {
-    _tx_id: u32,
+    tx_id: u32,
     client_id: u16,
-    amount: i64,
+    kind: BookingKind,
+    // Type of the transaction that created the booking.
+    tx_type: TxType,
+    amount: Amount,
+    currency: Currency,
+    // Client that received the funds of a transfer.
+    counterparty: Option<u16>,
+    metadata: TxMetadata,
     locked: bool,
     state: BookingState,
+    disputes: Vec<Dispute>,
+    // Sum of the open disputes.
+    disputed: Amount,
+    // Sum of the charged back disputes.
+    charged_back: Amount,
 }
 
 impl Booking {
-    pub fn new(tx_id: u32, client_id: u16, amount: i64) -> Self {
+    pub fn new(tx_id: u32, client_id: u16, tx_type: TxType, amount: Amount, currency: Currency) -> Self {
         Self {
-            _tx_id: tx_id,
+            tx_id,
             client_id,
+            kind: BookingKind::Transaction,
+            tx_type,
             amount,
+            currency,
+            counterparty: None,
+            metadata: TxMetadata::default(),
             locked: false,
             state: BookingState::Pristine,
+            disputes: Vec::new(),
+            disputed: Amount::ZERO,
+            charged_back: Amount::ZERO,
         }
     }
+    // Fee charged for the transaction `tx_id`. Fees can't be disputed.
+    pub fn new_fee(tx_id: u32, client_id: u16, tx_type: TxType, amount: Amount, currency: Currency) -> Self {
+        let mut b = Booking::new(tx_id, client_id, tx_type, amount, currency);
+        b.kind = BookingKind::Fee;
+        b.set_state_and_lock(BookingState::Normal);
+        b
+    }
+    // Interest accrued by the transaction `tx_id`, it can't be disputed either.
+    pub fn new_interest(tx_id: u32, client_id: u16, amount: Amount, currency: Currency) -> Self {
+        let mut b = Booking::new(tx_id, client_id, TxType::Accrue, amount, currency);
+        b.kind = BookingKind::Interest;
+        b.set_state_and_lock(BookingState::Normal);
+        b
+    }
+    pub fn set_counterparty(&mut self, client_id: u16) -> &mut Self {
+        self.counterparty = Some(client_id);
+        self
+    }
+    pub fn set_metadata(&mut self, metadata: TxMetadata) -> &mut Self {
+        self.metadata = metadata;
+        self
+    }
     pub fn set_state(&mut self, state: BookingState) -> &mut Self {
         self.state = state;
         self
@@ -38,16 +135,168 @@ impl Booking {
         self.locked = true;
         self
     }
+    pub fn get_tx_id(&self) -> u32 {
+        self.tx_id
+    }
+    pub fn get_kind(&self) -> BookingKind {
+        self.kind
+    }
+    pub fn get_tx_type(&self) -> TxType {
+        self.tx_type
+    }
     pub fn get_client_id(&self) -> u16 {
         self.client_id
     }
-    pub fn get_amount(&self) -> i64 {
+    pub fn get_amount(&self) -> Amount {
         self.amount
     }
+    pub fn get_currency(&self) -> &Currency {
+        &self.currency
+    }
+    pub fn get_metadata(&self) -> &TxMetadata {
+        &self.metadata
+    }
+    pub fn get_counterparty(&self) -> Option<u16> {
+        self.counterparty
+    }
+    // Client whose balance holds the funds of the booking and is affected by
+    // disputes, the receiver for transfers and the booking client otherwise.
+    pub fn get_holder(&self) -> u16 {
+        self.counterparty.unwrap_or(self.client_id)
+    }
     pub fn get_state(&self) -> BookingState {
         self.state
     }
     pub fn is_locked(&self) -> bool {
         self.locked
     }
+    pub fn get_disputed(&self) -> Amount {
+        self.disputed
+    }
+    pub fn get_charged_back(&self) -> Amount {
+        self.charged_back
+    }
+    pub fn get_disputes(&self) -> &[Dispute] {
+        &self.disputes
+    }
+    // Part of the booking that can still be disputed.
+    pub fn get_disputable(&self) -> LedgerResult<Amount> {
+        (self.amount - self.disputed)? - self.charged_back
+    }
+    pub fn get_open_dispute(&self, id: u32) -> LedgerResult<&Dispute> {
+        self.disputes.iter()
+            .find(|d| d.id == id && d.state == DisputeState::Open)
+            .ok_or_else(|| LedgerError::doesnt_exist(format!("open dispute {}", id)))
+    }
+    pub fn dispute_summaries(&self) -> Vec<DisputeSummary> {
+        self.disputes.iter()
+            .map(|d| DisputeSummary{
+                tx: self.tx_id,
+                client: self.client_id,
+                dispute: d.id,
+                amount: d.amount,
+                currency: self.currency.clone(),
+                state: d.state,
+                holder: self.get_holder(),
+                tx_type: self.tx_type,
+            })
+            .collect()
+    }
+    pub fn open_dispute(&mut self, id: u32, amount: Amount) -> LedgerResult<()> {
+        if self.disputes.iter().any(|d| d.id == id) {
+            return Err(LedgerError::repository_error(format!("dispute {} already exists", id)));
+        }
+        if amount <= Amount::ZERO || amount > self.get_disputable()? {
+            return Err(LedgerError::repository_error("invalid dispute amount"));
+        }
+
+        self.disputed = (self.disputed + amount)?;
+        self.disputes.push(Dispute{id, amount, state: DisputeState::Open});
+        Ok(())
+    }
+    // Closes an open dispute either as resolved or as charged back.
+    pub fn close_dispute(&mut self, id: u32, state: DisputeState) -> LedgerResult<Amount> {
+        let amount = self.get_open_dispute(id)?.amount;
+        let disputed = (self.disputed - amount)?;
+        let charged_back = match state {
+            DisputeState::Chargeback => (self.charged_back + amount)?,
+            _ => self.charged_back,
+        };
+
+        self.disputed = disputed;
+        self.charged_back = charged_back;
+        if let Some(d) = self.disputes.iter_mut().find(|d| d.id == id) {
+            d.state = state;
+        }
+
+        Ok(amount)
+    }
+    // Marks a charged back dispute as contested by the merchant.
+    pub fn represent_dispute(&mut self, id: u32) -> LedgerResult<Amount> {
+        self.move_dispute(id, &[DisputeState::Chargeback], DisputeState::Represented)
+    }
+    // Reverses a chargeback, with or without a representment. The reversed
+    // part of the booking stays charged back so it can't be disputed again.
+    pub fn reverse_chargeback(&mut self, id: u32) -> LedgerResult<Amount> {
+        self.move_dispute(id, &[DisputeState::Chargeback, DisputeState::Represented], DisputeState::Reversed)
+    }
+    fn move_dispute(&mut self, id: u32, from: &[DisputeState], to: DisputeState) -> LedgerResult<Amount> {
+        let d = self.disputes.iter_mut()
+            .find(|d| d.id == id && from.contains(&d.state))
+            .ok_or_else(|| LedgerError::doesnt_exist(format!("charged back dispute {}", id)))?;
+        d.state = to;
+
+        Ok(d.amount)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dom::{Amount, Booking, Currency, DisputeState, TxType};
+
+    #[test]
+    fn partial_disputes() {
+        let mut b = Booking::new(1, 1, TxType::Deposit, Amount::from(10_0000), Currency::default());
+
+        b.open_dispute(1, Amount::from(4_0000)).unwrap();
+        b.open_dispute(2, Amount::from(5_0000)).unwrap();
+        assert!(b.open_dispute(3, Amount::from(1_0001)).is_err());
+        assert!(b.open_dispute(2, Amount::from(1_0000)).is_err());
+        assert!(b.open_dispute(3, Amount::ZERO).is_err());
+        assert_eq!(Amount::from(9_0000), b.get_disputed());
+
+        assert_eq!(Amount::from(4_0000), b.close_dispute(1, DisputeState::Resolved).unwrap());
+        assert!(b.close_dispute(1, DisputeState::Chargeback).is_err());
+        assert_eq!(Amount::from(5_0000), b.close_dispute(2, DisputeState::Chargeback).unwrap());
+        assert_eq!(Amount::ZERO, b.get_disputed());
+        assert_eq!(Amount::from(5_0000), b.get_charged_back());
+
+        // Resolved part can be disputed again, charged back part can't.
+        assert!(b.open_dispute(3, Amount::from(5_0001)).is_err());
+        b.open_dispute(3, Amount::from(5_0000)).unwrap();
+        assert_eq!(Amount::ZERO, b.get_disputable().unwrap());
+    }
+
+    #[test]
+    fn chargeback_reversal() {
+        let mut b = Booking::new(1, 1, TxType::Deposit, Amount::from(10_0000), Currency::default());
+        b.open_dispute(1, Amount::from(4_0000)).unwrap();
+        b.open_dispute(2, Amount::from(6_0000)).unwrap();
+
+        // Only charged back disputes can be represented and reversed.
+        assert!(b.represent_dispute(1).is_err());
+        assert!(b.reverse_chargeback(1).is_err());
+
+        b.close_dispute(1, DisputeState::Chargeback).unwrap();
+        b.close_dispute(2, DisputeState::Chargeback).unwrap();
+        assert_eq!(Amount::from(4_0000), b.represent_dispute(1).unwrap());
+        assert!(b.represent_dispute(1).is_err());
+        assert_eq!(Amount::from(4_0000), b.reverse_chargeback(1).unwrap());
+        assert_eq!(Amount::from(6_0000), b.reverse_chargeback(2).unwrap());
+        assert!(b.reverse_chargeback(2).is_err());
+
+        let states: Vec<DisputeState> = b.dispute_summaries().iter().map(|d| d.state).collect();
+        assert_eq!(vec![DisputeState::Reversed, DisputeState::Reversed], states);
+        assert_eq!(Amount::ZERO, b.get_disputable().unwrap());
+    }
 }
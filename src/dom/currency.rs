@@ -0,0 +1,135 @@
+use std::{collections::HashMap, fmt};
+
+use serde::{Serialize, Deserialize};
+
+use crate::dom::{Amount, LedgerError, LedgerResult, SCALE};
+
+// Currency is identified by its code. Transactions that don't name a currency
+// are booked in the default currency which has an empty code, so the ledger
+// behaves as a single currency one unless told otherwise.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+#[serde(transparent)]
+pub struct Currency(String);
+
+impl Currency {
+    pub fn new<C: Into<String>>(code: C) -> Self {
+        Currency(code.into())
+    }
+    pub fn code(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+// A row of the currencies file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CurrencySpec {
+    pub currency: Currency,
+    pub scale: usize,
+}
+
+// Currencies known to the ledger with the number of decimal places each of
+// them allows. The default currency is always known and uses the full `SCALE`.
+// Amounts are stored at `SCALE` whatever their currency, so no currency can
+// declare more decimal places than that.
+#[derive(Debug, Clone)]
+pub struct Currencies {
+    scales: HashMap<Currency, usize>,
+}
+
+impl Default for Currencies {
+    fn default() -> Self {
+        Self {
+            scales: HashMap::from([(Currency::default(), SCALE)]),
+        }
+    }
+}
+
+impl Currencies {
+    pub fn new() -> Self {
+        Currencies::default()
+    }
+    pub fn add(&mut self, spec: CurrencySpec) -> LedgerResult<()> {
+        if spec.scale > SCALE {
+            return Err(LedgerError::excess_precision(format!(
+                "{} declares {} decimal places, at most {} are supported", spec.currency, spec.scale, SCALE
+            )));
+        }
+        self.scales.insert(spec.currency, spec.scale);
+
+        Ok(())
+    }
+    pub fn get_scale(&self, currency: &Currency) -> LedgerResult<usize> {
+        self.scales.get(currency)
+            .copied()
+            .ok_or_else(|| LedgerError::doesnt_exist(format!("currency {:?}", currency.code())))
+    }
+    // Checks that the amount is known and doesn't use more decimal places than
+    // its currency allows.
+    pub fn validate(&self, currency: &Currency, amount: Amount) -> LedgerResult<()> {
+        let scale = self.get_scale(currency)?;
+        if !amount.fits_scale(scale) {
+            return Err(LedgerError::excess_precision(format!("{} {}", amount, currency)));
+        }
+
+        Ok(())
+    }
+}
+
+impl TryFrom<Vec<CurrencySpec>> for Currencies {
+    type Error = LedgerError;
+
+    fn try_from(specs: Vec<CurrencySpec>) -> LedgerResult<Self> {
+        let mut currencies = Currencies::new();
+        for spec in specs {
+            currencies.add(spec)?;
+        }
+
+        Ok(currencies)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dom::{Amount, Currencies, Currency, CurrencySpec};
+
+    #[test]
+    fn deserialize_currencies_csv() {
+        let data = "currency, scale
+USD, 2
+JPY, 0";
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .trim(csv::Trim::All)
+            .from_reader(data.as_bytes());
+        let specs: Vec<CurrencySpec> = rdr.deserialize().collect::<Result<_, _>>().unwrap();
+        let currencies = Currencies::try_from(specs).unwrap();
+
+        assert_eq!(2, currencies.get_scale(&Currency::new("USD")).unwrap());
+        assert_eq!(0, currencies.get_scale(&Currency::new("JPY")).unwrap());
+        assert_eq!(4, currencies.get_scale(&Currency::default()).unwrap());
+        assert!(currencies.get_scale(&Currency::new("EUR")).is_err());
+    }
+
+    #[test]
+    fn validate_amount_scale() {
+        let currencies = Currencies::try_from(vec![
+            CurrencySpec{currency: Currency::new("USD"), scale: 2},
+            CurrencySpec{currency: Currency::new("JPY"), scale: 0},
+        ]).unwrap();
+
+        let usd = Currency::new("USD");
+        let jpy = Currency::new("JPY");
+        assert!(currencies.validate(&usd, Amount::from(1_2300)).is_ok());
+        assert!(currencies.validate(&usd, Amount::from(1_2340)).is_err());
+        assert!(currencies.validate(&jpy, Amount::from(-12_0000)).is_ok());
+        assert!(currencies.validate(&jpy, Amount::from(12_5000)).is_err());
+        assert!(currencies.validate(&Currency::default(), Amount::from(1)).is_ok());
+        assert!(Currencies::try_from(vec![CurrencySpec{currency: usd, scale: 5}]).is_err());
+    }
+}
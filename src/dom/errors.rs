@@ -5,6 +5,11 @@ pub enum LedgerErrorKind {
     DoesNotExist(String),
     RepositoryError(String),
     ServiceError(String),
+    MalformedAmount(String),
+    ExcessPrecision(String),
+    Overflow(String),
+    Conflict(String),
+    LimitExceeded(String),
 }
 
 impl Display for LedgerErrorKind {
@@ -15,6 +20,11 @@ impl Display for LedgerErrorKind {
             }
             LedgerErrorKind::RepositoryError(msg) => write!(fmt, "Repository error: {}", msg),
             LedgerErrorKind::ServiceError(msg) => write!(fmt, "Service error: {}", msg),
+            LedgerErrorKind::MalformedAmount(msg) => write!(fmt, "Malformed amount: {:?}", msg),
+            LedgerErrorKind::ExcessPrecision(msg) => write!(fmt, "Amount has too many decimal places: {}", msg),
+            LedgerErrorKind::Overflow(msg) => write!(fmt, "Amount overflow: {}", msg),
+            LedgerErrorKind::Conflict(msg) => write!(fmt, "Conflicting transaction: {}", msg),
+            LedgerErrorKind::LimitExceeded(msg) => write!(fmt, "Limit exceeded: {}", msg),
         }
     }
 }
@@ -42,6 +52,21 @@ impl LedgerError {
     pub fn service_error<M: Into<String>>(msg: M) -> Self {
         LedgerErrorKind::ServiceError(msg.into()).into_err()
     }
+    pub fn malformed_amount<M: Into<String>>(msg: M) -> Self {
+        LedgerErrorKind::MalformedAmount(msg.into()).into_err()
+    }
+    pub fn excess_precision<M: Into<String>>(msg: M) -> Self {
+        LedgerErrorKind::ExcessPrecision(msg.into()).into_err()
+    }
+    pub fn overflow<M: Into<String>>(msg: M) -> Self {
+        LedgerErrorKind::Overflow(msg.into()).into_err()
+    }
+    pub fn conflict<M: Into<String>>(msg: M) -> Self {
+        LedgerErrorKind::Conflict(msg.into()).into_err()
+    }
+    pub fn limit_exceeded<M: Into<String>>(msg: M) -> Self {
+        LedgerErrorKind::LimitExceeded(msg.into()).into_err()
+    }
     pub fn kind(&self) -> &LedgerErrorKind {
         &self.kind
     }
@@ -0,0 +1,107 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Serialize, Deserialize};
+
+use crate::dom::{AccountStatus, AccountSummary, Amount, Currency, DisputeState, DisputeSummary, LedgerResult, TxType};
+
+// Exposure of the ledger in one currency, or to one client in one currency
+// when `client` is set. Funds are the positive totals and negative balances
+// the negative ones, chargebacks are those still charged back to the client
+// holding the funds. `locked` is only set on the rows of a client whose
+// account is locked by a chargeback.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Exposure {
+    pub client: Option<u16>,
+    pub currency: Currency,
+    pub customer_funds: Amount,
+    pub held: Amount,
+    pub negative_balances: Amount,
+    pub chargebacks: u64,
+    pub chargeback_amount: Amount,
+    pub locked: bool,
+}
+
+impl Exposure {
+    fn new(client: Option<u16>, currency: &Currency) -> Self {
+        Exposure{
+            client,
+            currency: currency.clone(),
+            customer_funds: Amount::ZERO,
+            held: Amount::ZERO,
+            negative_balances: Amount::ZERO,
+            chargebacks: 0,
+            chargeback_amount: Amount::ZERO,
+            locked: false,
+        }
+    }
+    fn add_account(&mut self, a: &AccountSummary) -> LedgerResult<()> {
+        match a.total.is_negative() {
+            true => self.negative_balances = (self.negative_balances + a.total)?,
+            false => self.customer_funds = (self.customer_funds + a.total)?,
+        }
+        self.held = (self.held + a.held)?;
+        Ok(())
+    }
+    fn add_chargeback(&mut self, d: &DisputeSummary) -> LedgerResult<()> {
+        self.chargebacks += 1;
+        self.chargeback_amount = (self.chargeback_amount + d.amount)?;
+        Ok(())
+    }
+}
+
+// Chargeback losses and negative balances across the ledger, with a row
+// per currency and one per client and currency that has a negative balance,
+// chargebacks or an account locked by a chargeback. An account is locked
+// whatever the currency, so the locked accounts are counted once for the
+// whole ledger.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ExposureReport {
+    pub totals: Vec<Exposure>,
+    pub clients: Vec<Exposure>,
+    pub locked_accounts: u64,
+}
+
+impl ExposureReport {
+    pub fn new(accounts: &[AccountSummary], disputes: &[DisputeSummary]) -> LedgerResult<Self> {
+        let mut totals: BTreeMap<Currency, Exposure> = BTreeMap::new();
+        let mut clients: BTreeMap<(u16, Currency), Exposure> = BTreeMap::new();
+        let mut locked = BTreeSet::new();
+
+        for a in accounts {
+            totals.entry(a.currency.clone())
+                .or_insert_with(|| Exposure::new(None, &a.currency))
+                .add_account(a)?;
+            let client = clients.entry((a.client, a.currency.clone()))
+                .or_insert_with(|| Exposure::new(Some(a.client), &a.currency));
+            client.add_account(a)?;
+            if a.status == AccountStatus::ChargebackLocked {
+                client.locked = true;
+                locked.insert(a.client);
+            }
+        }
+        // A represented chargeback is being won back from the card network,
+        // and a withdrawal charged back is a claim paid to the client rather
+        // than a loss.
+        let charged_back = |d: &&DisputeSummary| d.state == DisputeState::Chargeback && d.tx_type != TxType::Withdrawal;
+        for d in disputes.iter().filter(charged_back) {
+            totals.entry(d.currency.clone())
+                .or_insert_with(|| Exposure::new(None, &d.currency))
+                .add_chargeback(d)?;
+            clients.entry((d.holder, d.currency.clone()))
+                .or_insert_with(|| Exposure::new(Some(d.holder), &d.currency))
+                .add_chargeback(d)?;
+        }
+
+        Ok(ExposureReport{
+            totals: totals.into_values().collect(),
+            clients: clients.into_values()
+                .filter(|e| e.negative_balances != Amount::ZERO || e.chargebacks > 0 || e.locked)
+                .collect(),
+            locked_accounts: locked.len() as u64,
+        })
+    }
+    // Totals first, then the clients, as rows of a single table.
+    pub fn rows(&self) -> impl Iterator<Item = &Exposure> {
+        self.totals.iter().chain(self.clients.iter())
+    }
+}
@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+
+use serde::{Serialize, Deserialize};
+
+use crate::dom::{Amount, Currency, LedgerResult, Rate, RoundingMode, TxType};
+
+// A row of the fees file. The fee is `flat` plus `rate` times the amount of
+// the booking, both are optional. A fee without a currency applies to every
+// currency that doesn't have a fee of its own, `flat` is then in the currency
+// of the booking.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FeeSpec {
+    pub tx_type: TxType,
+    #[serde(default)]
+    pub currency: Option<Currency>,
+    #[serde(default)]
+    pub flat: Option<Amount>,
+    #[serde(default)]
+    pub rate: Option<Rate>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FeeSchedule {
+    fees: HashMap<(TxType, Option<Currency>), FeeSpec>,
+    rounding: RoundingMode,
+}
+
+impl FeeSchedule {
+    pub fn new(rounding: RoundingMode) -> Self {
+        Self {
+            fees: HashMap::new(),
+            rounding,
+        }
+    }
+    pub fn from_specs<I: IntoIterator<Item = FeeSpec>>(specs: I, rounding: RoundingMode) -> Self {
+        let mut schedule = FeeSchedule::new(rounding);
+        for spec in specs {
+            schedule.add(spec);
+        }
+
+        schedule
+    }
+    pub fn add(&mut self, spec: FeeSpec) {
+        self.fees.insert((spec.tx_type, spec.currency.clone()), spec);
+    }
+    // Fee for a transaction of the given type and amount, zero if there is
+    // none. The rate part is rounded to the scale of the currency.
+    pub fn get_fee(&self, tx_type: TxType, currency: &Currency, amount: Amount, scale: usize) -> LedgerResult<Amount> {
+        let spec = match self.fees.get(&(tx_type, Some(currency.clone())))
+            .or_else(|| self.fees.get(&(tx_type, None))) {
+            Some(spec) => spec,
+            None => return Ok(Amount::ZERO),
+        };
+
+        let flat = spec.flat.unwrap_or_default();
+        let proportional = match spec.rate {
+            Some(rate) => rate.apply(amount, scale, self.rounding)?,
+            None => Amount::ZERO,
+        };
+
+        flat + proportional
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dom::{Amount, Currency, FeeSchedule, FeeSpec, RoundingMode, TxType};
+
+    #[test]
+    fn fee_from_csv() {
+        let data = "tx_type, currency, flat, rate
+withdrawal,       ,  0.5, 0.01
+withdrawal,    USD,  1.0,
+chargeback,       , 15.0,
+deposit,          ,     , 0.001";
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .trim(csv::Trim::All)
+            .from_reader(data.as_bytes());
+        let mut schedule = FeeSchedule::new(RoundingMode::HalfUp);
+        for spec in rdr.deserialize::<FeeSpec>() {
+            schedule.add(spec.unwrap());
+        }
+
+        let (default, usd) = (Currency::default(), Currency::new("USD"));
+        let cases = vec![
+            // 0.5 + 1% of 12.3456
+            (TxType::Withdrawal, &default, 12_3456, 4, 6235),
+            (TxType::Withdrawal, &usd, 12_3456, 2, 1_0000),
+            (TxType::Chargeback, &usd, 1_0000, 2, 15_0000),
+            // 0.1% of 12.3456 = 0.0123456
+            (TxType::Deposit, &default, 12_3456, 4, 123),
+            (TxType::Deposit, &usd, 12_3456, 2, 100),
+            (TxType::Dispute, &default, 12_3456, 4, 0),
+        ];
+
+        for (tx_type, currency, amount, scale, expected) in cases {
+            let fee = schedule.get_fee(tx_type, currency, Amount::from(amount), scale).unwrap();
+            assert_eq!(Amount::from(expected), fee, "{:?} {}", tx_type, currency);
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use std::{collections::HashMap, fmt, str::FromStr};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+
+use crate::dom::{Amount, Currency, LedgerError, LedgerResult, RoundingMode, SCALE};
+use crate::dom::amount::{div_round, parse_decimal};
+
+/// Number of fractional digits kept by `Rate`.
+pub const RATE_SCALE: usize = 12;
+
+// Exchange rate, the price of one unit of the source currency in the target
+// currency. Rates are kept as exact decimals with `RATE_SCALE` digits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rate(i128);
+
+impl Rate {
+    // Multiplies the amount by the rate and rounds the result to `scale`
+    // decimal places, using integer arithmetic only.
+    pub fn apply(&self, amount: Amount, scale: usize, mode: RoundingMode) -> LedgerResult<Amount> {
+        round_exact(self.mul_exact(amount)?, scale, mode)
+    }
+    // Product of the amount and the rate without any rounding, in units of
+    // `10^-(SCALE + RATE_SCALE)`.
+    pub(crate) fn mul_exact(&self, amount: Amount) -> LedgerResult<i128> {
+        (amount.to_i64() as i128).checked_mul(self.0)
+            .ok_or_else(|| LedgerError::overflow(format!("{} * {}", amount, self)))
+    }
+}
+
+// Rounds an exact product made by `Rate::mul_exact` to `scale` decimal places.
+pub(crate) fn round_exact(exact: i128, scale: usize, mode: RoundingMode) -> LedgerResult<Amount> {
+    let precision = 10_i128.pow(RATE_SCALE as u32);
+    let step = 10_i128.pow((SCALE - scale.min(SCALE)) as u32);
+
+    Amount::try_from(div_round(exact, precision * step, mode) * step)
+}
+
+impl FromStr for Rate {
+    type Err = LedgerError;
+
+    fn from_str(s: &str) -> LedgerResult<Self> {
+        let value = parse_decimal(s, RATE_SCALE)?;
+        if value <= 0 {
+            return Err(LedgerError::malformed_amount(s.trim()));
+        }
+
+        Ok(Rate(value))
+    }
+}
+
+impl fmt::Display for Rate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let precision = 10_i128.pow(RATE_SCALE as u32);
+        write!(f, "{}.{:0width$}", self.0 / precision, self.0 % precision, width = RATE_SCALE)
+    }
+}
+
+impl Serialize for Rate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Rate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(de::Error::custom)
+    }
+}
+
+// A row of the rates file. A rate without `effective_from` applies from the
+// very first transaction, otherwise from the given sequence number onwards.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RateSpec {
+    pub from: Currency,
+    pub to: Currency,
+    pub rate: Rate,
+    #[serde(default)]
+    pub effective_from: Option<u64>,
+}
+
+// Result of converting an amount between two currencies.
+// `residue` is what was lost (or gained, if negative) by rounding the
+// converted amount to the scale of the target currency, in the target currency.
+// The part of it below `SCALE` is left in `carry`, in units of
+// `10^-(SCALE + RATE_SCALE)`, for the next conversion into the same currency.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conversion {
+    pub from: Currency,
+    pub amount: Amount,
+    pub to: Currency,
+    pub converted: Amount,
+    pub residue: Amount,
+    pub carry: i128,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RateTable {
+    rates: HashMap<(Currency, Currency), Vec<(u64, Rate)>>,
+    rounding: RoundingMode,
+}
+
+impl RateTable {
+    pub fn new(rounding: RoundingMode) -> Self {
+        Self {
+            rates: HashMap::new(),
+            rounding,
+        }
+    }
+    pub fn from_specs<I: IntoIterator<Item = RateSpec>>(specs: I, rounding: RoundingMode) -> Self {
+        let mut table = RateTable::new(rounding);
+        for spec in specs {
+            table.add(spec);
+        }
+
+        table
+    }
+    pub fn add(&mut self, spec: RateSpec) {
+        let rates = self.rates.entry((spec.from, spec.to)).or_default();
+        rates.push((spec.effective_from.unwrap_or_default(), spec.rate));
+        rates.sort_by_key(|(effective_from, _)| *effective_from);
+    }
+    // Latest rate for the pair that's effective at the given sequence number.
+    pub fn get_rate(&self, from: &Currency, to: &Currency, seq: u64) -> LedgerResult<Rate> {
+        self.rates.get(&(from.clone(), to.clone()))
+            .and_then(|rates| rates.iter().rev().find(|(effective_from, _)| *effective_from <= seq))
+            .map(|(_, rate)| *rate)
+            .ok_or_else(|| LedgerError::doesnt_exist(format!("rate {:?}/{:?}", from.code(), to.code())))
+    }
+    // The converted amount is rounded to `to_scale` and the difference to the
+    // exact value, plus the `carry` of the previous conversion into the same
+    // currency, is reported as the residue. Whatever doesn't fit into `SCALE`
+    // is carried again, so the residues add up to the exact differences.
+    pub fn convert(&self, from: &Currency, amount: Amount, to: &Currency, to_scale: usize, seq: u64, carry: i128) -> LedgerResult<Conversion> {
+        if from == to {
+            return Err(LedgerError::service_error("can't convert a currency to itself"));
+        }
+        let rate = self.get_rate(from, to, seq)?;
+        let exact = rate.mul_exact(amount)?;
+        let converted = round_exact(exact, to_scale, self.rounding)?;
+
+        let precision = 10_i128.pow(RATE_SCALE as u32);
+        let residue = exact - converted.to_i64() as i128 * precision + carry;
+
+        Ok(Conversion {
+            from: from.clone(),
+            amount,
+            to: to.clone(),
+            converted,
+            residue: Amount::try_from(residue / precision)?,
+            carry: residue % precision,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dom::{Amount, Currency, RateSpec, RateTable, RoundingMode};
+
+    fn table(rounding: RoundingMode) -> RateTable {
+        let data = "from, to, rate, effective_from
+EUR, USD, 1.1, 
+EUR, USD, 1.123456789, 10
+USD, JPY, 150.5,";
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .trim(csv::Trim::All)
+            .from_reader(data.as_bytes());
+        let mut table = RateTable::new(rounding);
+        for spec in rdr.deserialize::<RateSpec>() {
+            table.add(spec.unwrap());
+        }
+
+        table
+    }
+
+    #[test]
+    fn rate_by_sequence_number() {
+        let t = table(RoundingMode::Down);
+        let (eur, usd) = (Currency::new("EUR"), Currency::new("USD"));
+
+        assert_eq!("1.1".parse().ok(), t.get_rate(&eur, &usd, 1).ok());
+        assert_eq!("1.1".parse().ok(), t.get_rate(&eur, &usd, 9).ok());
+        assert_eq!("1.123456789".parse().ok(), t.get_rate(&eur, &usd, 10).ok());
+        assert!(t.get_rate(&usd, &eur, 1).is_err());
+        assert!("0".parse::<crate::dom::Rate>().is_err());
+        assert!("-1.5".parse::<crate::dom::Rate>().is_err());
+    }
+
+    struct ConvertCase {
+        rounding: RoundingMode,
+        from: &'static str,
+        to: &'static str,
+        scale: usize,
+        amount: i64,
+        seq: u64,
+        converted: i64,
+        residue: i64,
+    }
+
+    #[test]
+    fn convert_with_residue() {
+        let cases = vec![
+            // 10.0001 EUR * 1.123456789 = 11.2346802356789
+            ConvertCase{rounding: RoundingMode::Down, from: "EUR", to: "USD", scale: 2, amount: 10_0001, seq: 10, converted: 11_2300, residue: 46},
+            ConvertCase{rounding: RoundingMode::HalfUp, from: "EUR", to: "USD", scale: 2, amount: 10_0001, seq: 10, converted: 11_2300, residue: 46},
+            ConvertCase{rounding: RoundingMode::Up, from: "EUR", to: "USD", scale: 2, amount: 10_0001, seq: 10, converted: 11_2400, residue: -53},
+            ConvertCase{rounding: RoundingMode::HalfEven, from: "EUR", to: "USD", scale: 2, amount: 10_0000, seq: 1, converted: 11_0000, residue: 0},
+            // 1.01 USD * 150.5 = 152.005
+            ConvertCase{rounding: RoundingMode::HalfEven, from: "USD", to: "JPY", scale: 0, amount: 1_0100, seq: 1, converted: 152_0000, residue: 50},
+            ConvertCase{rounding: RoundingMode::HalfUp, from: "USD", to: "JPY", scale: 0, amount: 1_0100, seq: 1, converted: 152_0000, residue: 50},
+        ];
+
+        for c in cases {
+            let (from, to) = (Currency::new(c.from), Currency::new(c.to));
+            let r = table(c.rounding).convert(&from, Amount::from(c.amount), &to, c.scale, c.seq, 0).unwrap();
+            assert_eq!(Amount::from(c.converted), r.converted, "{:?} {} {}", c.rounding, from, to);
+            assert_eq!(Amount::from(c.residue), r.residue, "{:?} {} {}", c.rounding, from, to);
+        }
+
+        let eur = Currency::new("EUR");
+        assert!(table(RoundingMode::Down).convert(&eur, Amount::from(1), &eur, 2, 1, 0).is_err());
+    }
+
+    #[test]
+    fn residue_below_scale_is_carried() {
+        let (gbp, usd) = (Currency::new("GBP"), Currency::new("USD"));
+        let mut t = RateTable::new(RoundingMode::Down);
+        t.add(RateSpec{from: gbp.clone(), to: usd.clone(), rate: "1.00001".parse().unwrap(), effective_from: None});
+
+        // 1 GBP * 1.00001 = 1.00001 USD, the 0.00001 left over by every
+        // conversion only adds up to a whole 0.0001 on the tenth one.
+        let mut residues = Vec::new();
+        let mut carry = 0;
+        for _ in 0..10 {
+            let r = t.convert(&gbp, Amount::from(1_0000), &usd, 2, 1, carry).unwrap();
+            assert_eq!(Amount::from(1_0000), r.converted);
+            residues.push(r.residue.to_i64());
+            carry = r.carry;
+        }
+        assert_eq!(vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 1], residues);
+        assert_eq!(0, carry);
+    }
+}
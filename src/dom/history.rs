@@ -0,0 +1,111 @@
+use serde::{Serialize, Deserialize, de::{IntoDeserializer, value}};
+
+use crate::dom::{Amount, Currency, Tx, TxMetadata, TxType};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BookingKind {
+    // Booking created by a client transaction.
+    Transaction,
+    // Fee charged by the ledger for a client transaction.
+    Fee,
+    // Interest paid by the ledger on a client balance.
+    Interest,
+}
+
+// Record of a change applied to a client account, in the order of
+// application. Rejected transactions don't make it into the history.
+// The metadata is the one of the transaction that made the change.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    pub seq: u64,
+    pub tx: u32,
+    pub client: u16,
+    #[serde(rename = "type")]
+    pub tx_type: TxType,
+    pub kind: BookingKind,
+    pub amount: Amount,
+    pub currency: Currency,
+    pub timestamp: Option<u64>,
+    pub reference: Option<String>,
+    pub memo: Option<String>,
+    pub batch: Option<String>,
+}
+
+// Transaction the ledger refused, with the reason. A row of the input that
+// couldn't be read has its line and whatever columns could still be read.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Reject {
+    pub tx: Option<u32>,
+    pub client: Option<u16>,
+    #[serde(rename = "type")]
+    pub tx_type: Option<TxType>,
+    pub amount: Option<String>,
+    pub timestamp: Option<u64>,
+    pub reference: Option<String>,
+    pub memo: Option<String>,
+    pub batch: Option<String>,
+    pub error: String,
+    pub line: Option<u64>,
+}
+
+impl Reject {
+    pub fn new(tx: &Tx, error: String) -> Self {
+        let TxMetadata{timestamp, reference, memo, batch} = tx.get_metadata();
+        Reject{
+            tx: Some(tx.tx_id),
+            client: Some(tx.client_id),
+            tx_type: Some(tx.tx_type),
+            amount: tx.get_submitted_amount(),
+            timestamp,
+            reference,
+            memo,
+            batch,
+            error,
+            line: None,
+        }
+    }
+    // `column` gives the raw value of a column of the row, if it has one.
+    pub fn unreadable(line: u64, column: impl Fn(&str) -> Option<String>, error: String) -> Self {
+        Reject{
+            tx: column("tx").and_then(|s| s.parse().ok()),
+            client: column("client").and_then(|s| s.parse().ok()),
+            tx_type: column("type")
+                .and_then(|s| TxType::deserialize(IntoDeserializer::<value::Error>::into_deserializer(s.as_str())).ok()),
+            amount: column("amount"),
+            timestamp: column("timestamp").and_then(|s| s.parse().ok()),
+            reference: column("reference"),
+            memo: column("memo"),
+            batch: column("batch"),
+            error,
+            line: Some(line),
+        }
+    }
+}
+
+// Back-office correction of a client balance, kept for the adjustments report.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Adjustment {
+    pub seq: u64,
+    pub tx: u32,
+    pub client: u16,
+    #[serde(rename = "type")]
+    pub tx_type: TxType,
+    pub amount: Amount,
+    pub currency: Currency,
+    pub timestamp: Option<u64>,
+    pub reference: String,
+    pub memo: String,
+}
+
+// Interest accrued on a client balance, kept for the accruals report.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Accrual {
+    pub seq: u64,
+    pub tx: u32,
+    pub client: u16,
+    pub period: String,
+    pub currency: Currency,
+    pub balance: Amount,
+    pub interest: Amount,
+}
@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+
+use serde::{Serialize, Deserialize};
+
+use crate::dom::{Amount, Currency, LedgerError, LedgerResult, Rate, RoundingMode};
+use crate::dom::fx::round_exact;
+
+// A row of the interest file. The rate applies to the part of the balance
+// from `from` up to the start of the next band, per accrual period. A band
+// without a currency applies to every currency that doesn't have bands of
+// its own.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct InterestSpec {
+    #[serde(default)]
+    pub currency: Option<Currency>,
+    pub from: Amount,
+    pub rate: Rate,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct InterestTable {
+    bands: HashMap<Option<Currency>, Vec<(Amount, Rate)>>,
+    rounding: RoundingMode,
+}
+
+impl InterestTable {
+    pub fn new(rounding: RoundingMode) -> Self {
+        Self {
+            bands: HashMap::new(),
+            rounding,
+        }
+    }
+    pub fn from_specs<I: IntoIterator<Item = InterestSpec>>(specs: I, rounding: RoundingMode) -> Self {
+        let mut table = InterestTable::new(rounding);
+        for spec in specs {
+            table.add(spec);
+        }
+
+        table
+    }
+    pub fn add(&mut self, spec: InterestSpec) {
+        let bands = self.bands.entry(spec.currency).or_default();
+        bands.retain(|(from, _)| *from != spec.from);
+        bands.push((spec.from, spec.rate));
+        bands.sort_by_key(|(from, _)| *from);
+    }
+    // Interest for one period on the given balance. Every band contributes
+    // its exact share and only the sum is rounded to the scale of the
+    // currency. Balances below the first band don't earn anything.
+    pub fn get_interest(&self, currency: &Currency, balance: Amount, scale: usize) -> LedgerResult<Amount> {
+        let bands = match self.bands.get(&Some(currency.clone()))
+            .or_else(|| self.bands.get(&None)) {
+            Some(bands) => bands,
+            None => return Ok(Amount::ZERO),
+        };
+
+        let mut exact: i128 = 0;
+        for (i, (from, rate)) in bands.iter().enumerate() {
+            if balance <= *from {
+                break;
+            }
+            let to = bands.get(i + 1).map_or(balance, |(next, _)| balance.min(*next));
+            exact = exact.checked_add(rate.mul_exact((to - *from)?)?)
+                .ok_or_else(|| LedgerError::overflow(format!("interest on {}", balance)))?;
+        }
+
+        round_exact(exact, scale, self.rounding)
+    }
+}
+
+// Interest is accrued per calendar month, written as YYYY-MM.
+pub fn check_period(period: &str) -> LedgerResult<()> {
+    let digits = |s: &str, len: usize| s.len() == len && s.bytes().all(|b| b.is_ascii_digit());
+    let valid = match period.split_once('-') {
+        Some((year, month)) => digits(year, 4) && digits(month, 2) && matches!(month.parse::<u8>(), Ok(1..=12)),
+        None => false,
+    };
+    if !valid {
+        return Err(LedgerError::service_error(format!("invalid period {:?}, expected YYYY-MM", period)));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dom::{Amount, Currency, InterestSpec, InterestTable, RoundingMode, check_period};
+
+    #[test]
+    fn tiered_interest_from_csv() {
+        let data = "currency,     from,  rate
+,                0,  0.001
+,             1000,  0.002
+,            10000,  0.003
+USD,           100,  0.0105";
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .trim(csv::Trim::All)
+            .from_reader(data.as_bytes());
+        let mut table = InterestTable::new(RoundingMode::HalfEven);
+        for spec in rdr.deserialize::<InterestSpec>() {
+            table.add(spec.unwrap());
+        }
+
+        let (default, usd) = (Currency::default(), Currency::new("USD"));
+        let cases = vec![
+            // 0.1% of 500
+            (&default, 500_0000, 4, 5000),
+            // 0.1% of 1000 + 0.2% of 500
+            (&default, 1500_0000, 4, 2_0000),
+            // 0.1% of 1000 + 0.2% of 9000 + 0.3% of 10000
+            (&default, 200_000_000, 4, 49_0000),
+            // 0.1% of 0.0123 = 0.0000123
+            (&default, 123, 4, 0),
+            (&default, -100_0000, 4, 0),
+            // 1.05% of 0.5 = 0.00525, rounded half even to two places
+            (&usd, 100_5000, 2, 100),
+            (&usd, 99_0000, 2, 0),
+        ];
+
+        for (currency, balance, scale, expected) in cases {
+            let interest = table.get_interest(currency, Amount::from(balance), scale).unwrap();
+            assert_eq!(Amount::from(expected), interest, "{} {}", currency, balance);
+        }
+    }
+
+    #[test]
+    fn periods_are_months() {
+        for period in ["2024-01", "1999-12"] {
+            assert!(check_period(period).is_ok(), "{}", period);
+        }
+        for period in ["", "2024", "2024-1", "2024-13", "2024-00", "24-01", "2024/01", "2024-+1", "2024-01-01"] {
+            assert!(check_period(period).is_err(), "{}", period);
+        }
+    }
+}
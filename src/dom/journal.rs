@@ -0,0 +1,189 @@
+use std::collections::BTreeMap;
+
+use serde::{Serialize, Deserialize};
+
+use crate::dom::{Amount, Currency, HouseAccount, HouseSummary, LedgerError, LedgerResult};
+
+// Accounts of the double-entry journal underneath the client balances. The
+// ledger owes every client its total funds, so client accounts are
+// liabilities, and the house accounts are the other side of every movement.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum JournalAccount {
+    // Funds owed to a client, one account per client and currency.
+    ClientLiability,
+    // Funds at the bank, what deposits bring in and withdrawals take out.
+    SettlementCash,
+    // Chargebacks paid out of settlement cash before they are recovered from
+    // the client, and withdrawals claimed back from the card network.
+    ChargebackLosses,
+    FeeIncome,
+    InterestExpense,
+    // Position of the house in the currencies clients convert between.
+    FxExchange,
+    FxResidue,
+    // Back-office corrections.
+    Adjustments,
+}
+
+// One side of a journal entry, only client accounts have a client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JournalSide {
+    pub account: JournalAccount,
+    pub client: Option<u16>,
+}
+
+impl JournalSide {
+    pub fn client(client: u16) -> Self {
+        JournalSide{account: JournalAccount::ClientLiability, client: Some(client)}
+    }
+    pub fn house(account: JournalAccount) -> Self {
+        JournalSide{account, client: None}
+    }
+}
+
+// Line of the journal. Every entry is a debit line and a credit line of the
+// same amount, with the sequence number and id of the transaction.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JournalEntry {
+    pub seq: u64,
+    pub tx: u32,
+    pub account: JournalAccount,
+    pub client: Option<u16>,
+    pub currency: Currency,
+    pub debit: Amount,
+    pub credit: Amount,
+}
+
+// Debits and credits of a journal account, the balance is the debits minus
+// the credits, so a client with funds has a negative balance.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TrialBalance {
+    pub account: JournalAccount,
+    pub client: Option<u16>,
+    pub currency: Currency,
+    pub debit: Amount,
+    pub credit: Amount,
+    pub balance: Amount,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Journal {
+    entries: Vec<JournalEntry>,
+}
+
+impl Journal {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    // Debits `debit` and credits `credit` with `amount`, a negative amount
+    // goes the other way. Nothing is posted for a zero amount.
+    pub fn post(&mut self, seq: u64, tx: u32, currency: &Currency, debit: JournalSide, credit: JournalSide, amount: Amount) -> LedgerResult<()> {
+        if amount == Amount::ZERO {
+            return Ok(());
+        }
+        let (debit, credit, amount) = match amount.is_negative() {
+            true => (credit, debit, (-amount)?),
+            false => (debit, credit, amount),
+        };
+
+        let line = |side: JournalSide, debit, credit| JournalEntry{
+            seq,
+            tx,
+            account: side.account,
+            client: side.client,
+            currency: currency.clone(),
+            debit,
+            credit,
+        };
+        self.entries.push(line(debit, amount, Amount::ZERO));
+        self.entries.push(line(credit, Amount::ZERO, amount));
+
+        Ok(())
+    }
+    pub fn get_entries(&self) -> &[JournalEntry] {
+        &self.entries
+    }
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    // Drops the entries past the first `len`, to undo what was posted since.
+    pub fn truncate(&mut self, len: usize) {
+        self.entries.truncate(len);
+    }
+    // Totals of every account by currency, account and client. Fails unless
+    // the balances sum to zero in every currency.
+    pub fn trial_balance(&self) -> LedgerResult<Vec<TrialBalance>> {
+        let mut totals: BTreeMap<(Currency, JournalAccount, Option<u16>), (Amount, Amount)> = BTreeMap::new();
+        for e in self.entries.iter() {
+            let (debit, credit) = totals.entry((e.currency.clone(), e.account, e.client)).or_default();
+            *debit = (*debit + e.debit)?;
+            *credit = (*credit + e.credit)?;
+        }
+
+        let mut sums: BTreeMap<Currency, Amount> = BTreeMap::new();
+        let mut rows = Vec::new();
+        for ((currency, account, client), (debit, credit)) in totals {
+            let balance = (debit - credit)?;
+            let sum = sums.entry(currency.clone()).or_default();
+            *sum = (*sum + balance)?;
+            rows.push(TrialBalance{account, client, currency, debit, credit, balance});
+        }
+
+        if let Some((currency, _)) = sums.iter().find(|(_, sum)| **sum != Amount::ZERO) {
+            return Err(LedgerError::service_error(format!("books don't balance in {}", currency)));
+        }
+        Ok(rows)
+    }
+    // Balances of the house accounts, by account and currency. Fee income
+    // and the FX residue are credited, so their balance is the credits
+    // minus the debits, the interest expense is debited.
+    pub fn house_balances(&self) -> LedgerResult<Vec<HouseSummary>> {
+        let mut house = Vec::new();
+        for row in self.trial_balance()? {
+            let (account, balance) = match row.account {
+                JournalAccount::FxResidue => (HouseAccount::FxResidue, (-row.balance)?),
+                JournalAccount::FeeIncome => (HouseAccount::FeeIncome, (-row.balance)?),
+                JournalAccount::InterestExpense => (HouseAccount::InterestExpense, row.balance),
+                _ => continue,
+            };
+            house.push(HouseSummary{account, currency: row.currency, balance});
+        }
+        house.sort_by(|a, b| (a.account, &a.currency).cmp(&(b.account, &b.currency)));
+
+        Ok(house)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dom::{Amount, Currency, Journal, JournalAccount, JournalSide};
+
+    #[test]
+    fn trial_balance() {
+        let (usd, eur) = (Currency::new("USD"), Currency::new("EUR"));
+        let cash = JournalSide::house(JournalAccount::SettlementCash);
+        let fees = JournalSide::house(JournalAccount::FeeIncome);
+
+        let mut journal = Journal::new();
+        journal.post(1, 1, &usd, cash, JournalSide::client(1), Amount::from(10_0000)).unwrap();
+        journal.post(1, 1, &usd, JournalSide::client(1), fees, Amount::from(1000)).unwrap();
+        journal.post(2, 2, &eur, JournalSide::client(2), cash, Amount::from(-5_0000)).unwrap();
+        journal.post(3, 3, &eur, JournalSide::client(2), cash, Amount::ZERO).unwrap();
+        assert_eq!(6, journal.get_entries().len());
+
+        let rows: Vec<_> = journal.trial_balance().unwrap().into_iter()
+            .map(|r| (r.currency.to_string(), r.account, r.client, r.debit.to_i64(), r.credit.to_i64(), r.balance.to_i64()))
+            .collect();
+        assert_eq!(vec![
+            ("EUR".to_string(), JournalAccount::ClientLiability, Some(2), 0, 5_0000, -5_0000),
+            ("EUR".to_string(), JournalAccount::SettlementCash, None, 5_0000, 0, 5_0000),
+            ("USD".to_string(), JournalAccount::ClientLiability, Some(1), 1000, 10_0000, -9_9000),
+            ("USD".to_string(), JournalAccount::SettlementCash, None, 10_0000, 0, 10_0000),
+            ("USD".to_string(), JournalAccount::FeeIncome, None, 0, 1000, -1000),
+        ], rows);
+    }
+}
@@ -0,0 +1,187 @@
+use std::{collections::HashMap, fmt};
+
+use serde::{Serialize, Deserialize};
+
+use crate::dom::{Amount, Currency, LedgerError, LedgerResult};
+
+// Unit of the window withdrawals are counted in, either the sequence numbers
+// of the transactions or their timestamps in seconds.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowUnit {
+    #[default]
+    Transactions,
+    Seconds,
+}
+
+// A row of the limits file, the risk limits on withdrawals of the clients in
+// a tier. Every limit is optional. Count and total are taken over the last
+// `window` transactions or seconds including the withdrawal being checked.
+// Limits without a currency apply to every currency that doesn't have limits
+// of its own, clients without a tier are in the tier with an empty name.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LimitSpec {
+    #[serde(default)]
+    pub tier: String,
+    #[serde(default)]
+    pub currency: Option<Currency>,
+    #[serde(default)]
+    pub max_withdrawal: Option<Amount>,
+    #[serde(default)]
+    pub max_count: Option<u32>,
+    #[serde(default)]
+    pub max_total: Option<Amount>,
+    #[serde(default)]
+    pub window: u64,
+    #[serde(default)]
+    pub window_unit: WindowUnit,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitKind {
+    MaxWithdrawal,
+    MaxCount,
+    MaxTotal,
+}
+
+impl fmt::Display for LimitKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LimitKind::MaxWithdrawal => write!(f, "max_withdrawal"),
+            LimitKind::MaxCount => write!(f, "max_count"),
+            LimitKind::MaxTotal => write!(f, "max_total"),
+        }
+    }
+}
+
+// A withdrawal as far as the limits are concerned.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WithdrawalRecord {
+    pub seq: u64,
+    pub timestamp: Option<u64>,
+    pub currency: Currency,
+    pub amount: Amount,
+}
+
+// Number of times a client has been refused for breaking a limit.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LimitViolation {
+    pub client: u16,
+    pub tier: String,
+    pub limit: LimitKind,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LimitTable {
+    limits: HashMap<(String, Option<Currency>), LimitSpec>,
+}
+
+impl LimitTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn from_specs<I: IntoIterator<Item = LimitSpec>>(specs: I) -> Self {
+        let mut table = LimitTable::new();
+        for spec in specs {
+            table.add(spec);
+        }
+
+        table
+    }
+    pub fn add(&mut self, spec: LimitSpec) {
+        self.limits.insert((spec.tier.clone(), spec.currency.clone()), spec);
+    }
+    pub fn get_limits(&self, tier: &str, currency: &Currency) -> Option<&LimitSpec> {
+        self.limits.get(&(tier.to_string(), Some(currency.clone())))
+            .or_else(|| self.limits.get(&(tier.to_string(), None)))
+    }
+    // The first limit a withdrawal would break given the earlier withdrawals
+    // of the client, if any. A window in seconds needs the timestamp of the
+    // withdrawal, earlier withdrawals without one are outside of it.
+    pub fn check(&self, tier: &str, withdrawal: &WithdrawalRecord, past: &[WithdrawalRecord]) -> LedgerResult<Option<LimitKind>> {
+        let Some(spec) = self.get_limits(tier, &withdrawal.currency) else {
+            return Ok(None);
+        };
+
+        if spec.max_withdrawal.is_some_and(|max| withdrawal.amount > max) {
+            return Ok(Some(LimitKind::MaxWithdrawal));
+        }
+        if spec.max_count.is_none() && spec.max_total.is_none() {
+            return Ok(None);
+        }
+
+        let now = match spec.window_unit {
+            WindowUnit::Transactions => withdrawal.seq,
+            WindowUnit::Seconds => withdrawal.timestamp
+                .ok_or_else(|| LedgerError::service_error("missing timestamp"))?,
+        };
+        let start = now.saturating_sub(spec.window);
+        let in_window = |w: &WithdrawalRecord| match spec.window_unit {
+            WindowUnit::Transactions => w.seq > start,
+            WindowUnit::Seconds => w.timestamp.is_some_and(|t| t > start),
+        };
+
+        let mut count = 1;
+        let mut total = withdrawal.amount;
+        for w in past.iter().filter(|w| w.currency == withdrawal.currency && in_window(w)) {
+            count += 1;
+            total = (total + w.amount)?;
+        }
+
+        if spec.max_count.is_some_and(|max| count > max) {
+            return Ok(Some(LimitKind::MaxCount));
+        }
+        if spec.max_total.is_some_and(|max| total > max) {
+            return Ok(Some(LimitKind::MaxTotal));
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dom::{Amount, Currency, LimitKind, LimitSpec, LimitTable, WithdrawalRecord};
+
+    #[test]
+    fn limits_from_csv() {
+        let data = "tier, currency, max_withdrawal, max_count, max_total, window, window_unit
+    ,         ,          100.0,         2,          ,     10, transactions
+    ,      USD,               ,          ,     150.0,     60, seconds
+gold,         ,         1000.0,          ,          ,      0, transactions";
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .trim(csv::Trim::All)
+            .from_reader(data.as_bytes());
+        let table = LimitTable::from_specs(rdr.deserialize::<LimitSpec>().map(|s| s.unwrap()));
+
+        let (default, usd) = (Currency::default(), Currency::new("USD"));
+        let w = |seq, timestamp, currency: &Currency, amount| WithdrawalRecord{
+            seq,
+            timestamp,
+            currency: currency.clone(),
+            amount: Amount::from(amount),
+        };
+        let past = vec![
+            w(1, Some(1_000), &default, 50_0000),
+            w(5, Some(1_010), &default, 50_0000),
+            w(8, Some(1_050), &usd, 100_0000),
+        ];
+        let cases = vec![
+            ("", w(9, None, &default, 100_0001), Some(LimitKind::MaxWithdrawal)),
+            ("", w(9, None, &default, 10_0000), Some(LimitKind::MaxCount)),
+            ("", w(11, None, &default, 10_0000), None),
+            ("", w(9, Some(1_060), &usd, 50_0000), None),
+            ("", w(9, Some(1_060), &usd, 50_0001), Some(LimitKind::MaxTotal)),
+            ("", w(9, Some(1_110), &usd, 150_0000), None),
+            ("gold", w(9, None, &default, 1000_0000), None),
+            ("silver", w(9, None, &default, 1000_0001), None),
+        ];
+
+        for (tier, withdrawal, expected) in cases {
+            assert_eq!(expected, table.check(tier, &withdrawal, &past).unwrap(), "{} {:?}", tier, withdrawal);
+        }
+        assert!(table.check("", &w(9, None, &usd, 1_0000), &past).is_err());
+    }
+}
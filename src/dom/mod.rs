@@ -1,13 +1,35 @@
 mod account;
+mod admin;
 mod amount;
 mod booking;
+mod currency;
 mod errors;
+mod exposure;
+mod fee;
+mod fx;
+mod history;
+mod interest;
+mod journal;
+mod limit;
+mod policy;
+mod risk;
 mod service;
 mod tx;
 
 pub use account::*;
+pub use admin::*;
 pub use amount::*;
 pub use booking::*;
+pub use currency::*;
 pub use errors::*;
+pub use exposure::*;
+pub use fee::*;
+pub use fx::*;
+pub use history::*;
+pub use interest::*;
+pub use journal::*;
+pub use limit::*;
+pub use policy::*;
+pub use risk::*;
 pub use service::*;
 pub use tx::*;
\ No newline at end of file
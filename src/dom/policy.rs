@@ -0,0 +1,81 @@
+use serde::{Serialize, Deserialize};
+
+// Business rules that differ between ledgers running on the same engine.
+// The defaults keep the behaviour of a plain ledger. Policies are usually
+// read from a TOML file where every setting is optional.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LedgerPolicy {
+    // Whether accounts have to be opened by an `open` transaction or the
+    // accounts file before taking part in any other transaction.
+    pub strict_accounts: bool,
+    // Whether an adjustment debit may take the available balance below zero.
+    pub allow_negative_adjustments: bool,
+    // Whether a chargeback may take the balance below zero, which happens
+    // when the disputed funds have been spent in the meantime.
+    pub allow_negative_chargebacks: bool,
+    // Whether deposits and withdrawals with a negative amount are booked as
+    // they are instead of being refused. Other transactions never are.
+    pub allow_negative_amounts: bool,
+    // Whether amounts with more decimal places than their currency allows
+    // are truncated instead of being refused.
+    pub truncate_excess_precision: bool,
+    // Whether withdrawals can be disputed. A disputed withdrawal is a claim
+    // of the client held as a pending credit until it's resolved.
+    pub disputable_withdrawals: bool,
+    // Whether a dispute may take the available balance past the credit
+    // limit of the account holding the disputed funds.
+    pub disputes_past_credit_limit: bool,
+    // Whether reversing a chargeback unlocks the account it locked.
+    pub unlock_on_chargeback_reversal: bool,
+    // Number of transactions after which an authorization that hasn't been
+    // captured or voided expires, authorizations never expire if not set.
+    pub authorization_expiry: Option<u64>,
+    // Whether deposits arrive pending and have to be settled before the
+    // funds can be spent.
+    pub pending_deposits: bool,
+    // Number of transactions after which a pending deposit settles by
+    // itself, pending deposits wait for a `settle` if not set.
+    pub settlement_delay: Option<u64>,
+}
+
+impl Default for LedgerPolicy {
+    fn default() -> Self {
+        Self {
+            strict_accounts: false,
+            allow_negative_adjustments: false,
+            allow_negative_chargebacks: true,
+            allow_negative_amounts: false,
+            truncate_excess_precision: false,
+            disputable_withdrawals: false,
+            disputes_past_credit_limit: true,
+            unlock_on_chargeback_reversal: false,
+            authorization_expiry: None,
+            pending_deposits: false,
+            settlement_delay: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dom::LedgerPolicy;
+
+    #[test]
+    fn deserialize_policy_toml() {
+        let policy: LedgerPolicy = toml::from_str("
+allow_negative_chargebacks = false
+disputable_withdrawals = true
+authorization_expiry = 10
+").unwrap();
+        assert_eq!(LedgerPolicy{
+            allow_negative_chargebacks: false,
+            disputable_withdrawals: true,
+            authorization_expiry: Some(10),
+            ..LedgerPolicy::default()
+        }, policy);
+
+        assert_eq!(LedgerPolicy::default(), toml::from_str::<LedgerPolicy>("").unwrap());
+        assert!(toml::from_str::<LedgerPolicy>("disputable_withdrawal = true").is_err());
+    }
+}
@@ -0,0 +1,189 @@
+use std::collections::VecDeque;
+
+use serde::{Serialize, Deserialize};
+
+use crate::dom::{Amount, TxType};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RiskRuleKind {
+    // Disputes of the client above `threshold` percent of its deposits,
+    // evaluated on every dispute.
+    DisputeRatio,
+    // More than `threshold` chargebacks, evaluated on every chargeback.
+    ChargebackCount,
+    // A withdrawal, outgoing transfer or capture within `window`
+    // transactions after a deposit of at least `amount`.
+    WithdrawalAfterDeposit,
+}
+
+// What happens to the account of a client that triggers a rule. A flag only
+// produces the event, a frozen account keeps accepting deposits but nothing
+// leaves it, and a locked account refuses everything. Frozen and locked
+// accounts are released by an `unlock`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RiskAction {
+    Flag,
+    Freeze,
+    Lock,
+}
+
+// A row of the risk rules file, the columns a rule doesn't use are left empty.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RiskRule {
+    pub id: String,
+    pub kind: RiskRuleKind,
+    #[serde(default)]
+    pub threshold: Option<u64>,
+    #[serde(default)]
+    pub amount: Option<Amount>,
+    #[serde(default)]
+    pub window: Option<u64>,
+    pub action: RiskAction,
+}
+
+// Record of a rule triggered by a transaction of a client.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RiskEvent {
+    pub seq: u64,
+    pub tx: u32,
+    pub client: u16,
+    pub rule: String,
+    pub action: RiskAction,
+    pub timestamp: Option<u64>,
+}
+
+// Activity of a client as far as the risk rules are concerned.
+#[derive(Debug, Clone, Default)]
+pub struct RiskActivity {
+    deposits: u64,
+    disputes: u64,
+    chargebacks: u64,
+    // Sequence number and amount of the recent deposits, older ones are
+    // dropped once no rule's window can reach them.
+    recent_deposits: VecDeque<(u64, Amount)>,
+}
+
+impl RiskActivity {
+    pub fn record(&mut self, seq: u64, tx_type: TxType, amount: Amount, horizon: u64) {
+        match tx_type {
+            TxType::Deposit => {
+                self.deposits += 1;
+                self.recent_deposits.push_back((seq, amount));
+            },
+            TxType::Dispute => self.disputes += 1,
+            TxType::Chargeback => self.chargebacks += 1,
+            _ => {},
+        }
+
+        while self.recent_deposits.front().is_some_and(|(s, _)| s + horizon < seq) {
+            self.recent_deposits.pop_front();
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RiskRules {
+    rules: Vec<RiskRule>,
+}
+
+impl RiskRules {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn from_specs<I: IntoIterator<Item = RiskRule>>(rules: I) -> Self {
+        let mut table = RiskRules::new();
+        for rule in rules {
+            table.add(rule);
+        }
+
+        table
+    }
+    pub fn add(&mut self, rule: RiskRule) {
+        self.rules.push(rule);
+    }
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+    // Number of transactions deposits have to be remembered for.
+    pub fn get_horizon(&self) -> u64 {
+        self.rules.iter()
+            .filter(|r| r.kind == RiskRuleKind::WithdrawalAfterDeposit)
+            .filter_map(|r| r.window)
+            .max()
+            .unwrap_or_default()
+    }
+    // Rules triggered by a transaction, given the activity of the client
+    // including that transaction.
+    pub fn evaluate(&self, activity: &RiskActivity, seq: u64, tx_type: TxType) -> Vec<&RiskRule> {
+        self.rules.iter()
+            .filter(|r| {
+                let threshold = r.threshold.unwrap_or_default();
+                match (r.kind, tx_type) {
+                    (RiskRuleKind::DisputeRatio, TxType::Dispute) =>
+                        activity.disputes * 100 > threshold * activity.deposits,
+                    (RiskRuleKind::ChargebackCount, TxType::Chargeback) =>
+                        activity.chargebacks > threshold,
+                    (RiskRuleKind::WithdrawalAfterDeposit, tx_type) if tx_type.is_outflow() => {
+                        let start = seq.saturating_sub(r.window.unwrap_or_default());
+                        let amount = r.amount.unwrap_or_default();
+                        activity.recent_deposits.iter().any(|(s, a)| *s >= start && *a >= amount)
+                    },
+                    _ => false,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dom::{Amount, RiskActivity, RiskRule, RiskRules, TxType};
+
+    #[test]
+    fn rules_from_csv() {
+        let data = "id,        kind,                     threshold, amount, window, action
+disputes,  dispute_ratio,            50,        ,       ,       flag
+chargebacks, chargeback_count,       1,         ,       ,       lock
+cash_out,  withdrawal_after_deposit, ,          100.0,  2,      freeze";
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .trim(csv::Trim::All)
+            .from_reader(data.as_bytes());
+        let rules = RiskRules::from_specs(rdr.deserialize::<RiskRule>().map(|r| r.unwrap()));
+        let horizon = rules.get_horizon();
+        assert_eq!(2, horizon);
+
+        let mut activity = RiskActivity::default();
+        let mut triggered = Vec::new();
+        let txs = vec![
+            (TxType::Deposit, 100_0000),
+            (TxType::Deposit, 10_0000),
+            (TxType::Dispute, 0),
+            (TxType::Withdrawal, 1_0000),
+            (TxType::Dispute, 0),
+            (TxType::Chargeback, 0),
+            (TxType::Withdrawal, 1_0000),
+            (TxType::Chargeback, 0),
+        ];
+        for (seq, (tx_type, amount)) in txs.into_iter().enumerate() {
+            let seq = seq as u64 + 1;
+            activity.record(seq, tx_type, Amount::from(amount), horizon);
+            for rule in rules.evaluate(&activity, seq, tx_type) {
+                triggered.push((seq, rule.id.as_str()));
+            }
+        }
+
+        assert_eq!(vec![(5, "disputes"), (8, "chargebacks")], triggered);
+
+        let mut activity = RiskActivity::default();
+        activity.record(1, TxType::Deposit, Amount::from(100_0000), horizon);
+        activity.record(3, TxType::Withdrawal, Amount::from(1_0000), horizon);
+        assert_eq!(vec!["cash_out"], rules.evaluate(&activity, 3, TxType::Withdrawal).iter().map(|r| r.id.as_str()).collect::<Vec<_>>());
+        for tx_type in [TxType::Transfer, TxType::Capture] {
+            assert_eq!(vec!["cash_out"], rules.evaluate(&activity, 3, tx_type).iter().map(|r| r.id.as_str()).collect::<Vec<_>>());
+        }
+        assert!(rules.evaluate(&activity, 3, TxType::Convert).is_empty());
+    }
+}
@@ -1,12 +1,29 @@
-use super::{LedgerResult, AccountSummary, Tx};
+use super::{LedgerResult, AccountSummary, Accrual, Adjustment, DisputeSummary, ExposureReport, HistoryEntry, HouseSummary, JournalEntry, LimitViolation, RiskEvent, StatusChange, TrialBalance, Tx, Unlock};
 use async_trait::async_trait;
 
 #[async_trait]
 pub trait AccountService {
     async fn dump_accounts(&self) -> LedgerResult<Vec<AccountSummary>>;
+    async fn dump_house_accounts(&self) -> LedgerResult<Vec<HouseSummary>>;
+    async fn dump_unlocks(&self) -> LedgerResult<Vec<Unlock>>;
+    async fn dump_status_changes(&self) -> LedgerResult<Vec<StatusChange>>;
 }
 
 #[async_trait]
 pub trait BookingService {
     async fn process_tx(&self, tx: Tx) -> LedgerResult<()>;
+    async fn process_batch(&self, batch: &str, txs: Vec<Tx>) -> LedgerResult<()>;
+    async fn dump_history(&self) -> LedgerResult<Vec<HistoryEntry>>;
+    async fn dump_adjustments(&self) -> LedgerResult<Vec<Adjustment>>;
+    async fn dump_disputes(&self) -> LedgerResult<Vec<DisputeSummary>>;
+    async fn dump_accruals(&self) -> LedgerResult<Vec<Accrual>>;
+    async fn dump_violations(&self) -> LedgerResult<Vec<LimitViolation>>;
+    async fn dump_risk_events(&self) -> LedgerResult<Vec<RiskEvent>>;
+    async fn dump_journal(&self) -> LedgerResult<Vec<JournalEntry>>;
+    async fn trial_balance(&self) -> LedgerResult<Vec<TrialBalance>>;
+}
+
+#[async_trait]
+pub trait ReportService {
+    async fn exposure_report(&self) -> LedgerResult<ExposureReport>;
 }
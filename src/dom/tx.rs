@@ -1,7 +1,7 @@
-use crate::dom::Amount;
+use crate::dom::{AccountStatus, Amount, Currency, LedgerResult, ReasonCode};
 use serde::{Serialize, Deserialize};
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[serde(rename_all = "snake_case")]
 pub enum TxType {
     Deposit,
@@ -9,9 +9,94 @@ pub enum TxType {
     Dispute,
     Resolve,
     Chargeback,
+    Convert,
+    Transfer,
+    Unlock,
+    AdjustmentCredit,
+    AdjustmentDebit,
+    Representment,
+    ChargebackReversal,
+    Authorize,
+    Capture,
+    Void,
+    Settle,
+    RejectSettlement,
+    Accrue,
+    CreditLimit,
+    SetStatus,
+    Open,
+    Close,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+impl TxType {
+    // Whether the transaction starts a new booking rather than referring to
+    // an existing one.
+    pub fn creates_booking(&self) -> bool {
+        matches!(self,
+            TxType::Deposit
+            | TxType::Withdrawal
+            | TxType::Convert
+            | TxType::Transfer
+            | TxType::AdjustmentCredit
+            | TxType::AdjustmentDebit
+            | TxType::Authorize
+        )
+    }
+    // Whether the transaction follows up on a chargeback, these are allowed
+    // on accounts locked by the chargeback.
+    pub fn follows_chargeback(&self) -> bool {
+        matches!(self, TxType::Representment | TxType::ChargebackReversal)
+    }
+    // Whether the transaction only concludes on funds an earlier one set
+    // aside, these are allowed whatever the status of the account.
+    pub fn concludes_reserved(&self) -> bool {
+        matches!(self, TxType::Void | TxType::Settle | TxType::RejectSettlement)
+    }
+    // Whether the transaction takes funds out of the account of the client,
+    // these are held to the withdrawal limits and the risk rules alike.
+    pub fn is_outflow(&self) -> bool {
+        matches!(self, TxType::Withdrawal | TxType::Transfer | TxType::Capture)
+    }
+}
+
+// Identifies what a transaction refers to. Transactions that create a
+// booking are identified by the tx id alone, the others also by their type
+// and the dispute they refer to, as they share the tx id of the booking.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TxKey {
+    pub tx_id: u32,
+    pub tx_type: Option<TxType>,
+    pub dispute: Option<u32>,
+}
+
+// Everything about a transaction that changes its effect on the ledger, two
+// transactions with the same key and fingerprint are the same transaction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TxFingerprint {
+    pub client_id: u16,
+    pub tx_type: TxType,
+    pub amount: Option<Amount>,
+    pub currency: Option<Currency>,
+    pub to_currency: Option<Currency>,
+    pub to_client: Option<u16>,
+    pub period: Option<String>,
+    pub status: Option<AccountStatus>,
+    pub operator: Option<String>,
+    pub reason: Option<ReasonCode>,
+    pub review_period: Option<u64>,
+}
+
+// Upstream details of a transaction that the ledger only carries along, so
+// that bookings and reports can be traced back to the original payment.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct TxMetadata {
+    pub timestamp: Option<u64>,
+    pub reference: Option<String>,
+    pub memo: Option<String>,
+    pub batch: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Tx {
     #[serde(rename = "tx")]
     pub tx_id: u32,
@@ -19,21 +104,169 @@ pub struct Tx {
     pub client_id: u16,
     #[serde(rename = "type")]
     pub tx_type: TxType,
-    #[serde(rename = "amount")]
+    // The amount is read as it is and parsed when the transaction is
+    // processed, as the policy decides what happens to excess decimal places.
+    #[serde(rename(serialize = "amount"), skip_deserializing)]
     pub amount: Option<Amount>,
+    #[serde(rename(deserialize = "amount"), default, skip_serializing)]
+    pub raw_amount: Option<String>,
+    #[serde(rename = "currency", default)]
+    pub currency: Option<Currency>,
+    // Currency a `convert` transaction converts into.
+    #[serde(rename = "to_currency", default)]
+    pub to_currency: Option<Currency>,
+    // Client a `transfer` transaction sends the funds to.
+    #[serde(rename = "to_client", default)]
+    pub to_client: Option<u16>,
+    // Operator and reason of administrative transactions, such as a
+    // `credit_limit` that sets the credit line in `currency` to `amount`.
+    #[serde(rename = "operator", default)]
+    pub operator: Option<String>,
+    #[serde(rename = "reason", default)]
+    pub reason: Option<ReasonCode>,
+    // Status a `set_status` transaction moves the account to.
+    #[serde(rename = "status", default)]
+    pub status: Option<AccountStatus>,
+    // Number of transactions after an `unlock` during which the account
+    // doesn't allow withdrawals.
+    #[serde(rename = "review_period", default)]
+    pub review_period: Option<u64>,
+    // Time the transaction happened upstream, in seconds since the epoch.
+    #[serde(rename = "timestamp", default)]
+    pub timestamp: Option<u64>,
+    // External reference and free text note, mandatory for adjustments.
+    #[serde(rename = "reference", default)]
+    pub reference: Option<String>,
+    #[serde(rename = "memo", default)]
+    pub memo: Option<String>,
+    // Batch the transaction is a leg of, consecutive rows of the same batch
+    // are applied all together or not at all.
+    #[serde(rename = "batch", default)]
+    pub batch: Option<String>,
+    // Id of the dispute a dispute, resolve or chargeback refers to, for
+    // running several partial disputes against one booking.
+    #[serde(rename = "dispute", default)]
+    pub dispute: Option<u32>,
+    // Accrual period of an `accrue` transaction as YYYY-MM, interest is
+    // accrued once per client, currency and period.
+    #[serde(rename = "period", default)]
+    pub period: Option<String>,
+}
+
+impl Tx {
+    pub fn new(tx_id: u32, client_id: u16, tx_type: TxType, amount: Option<Amount>) -> Self {
+        Self {
+            tx_id,
+            client_id,
+            tx_type,
+            amount,
+            raw_amount: None,
+            currency: None,
+            to_currency: None,
+            to_client: None,
+            operator: None,
+            reason: None,
+            status: None,
+            review_period: None,
+            timestamp: None,
+            reference: None,
+            memo: None,
+            batch: None,
+            dispute: None,
+            period: None,
+        }
+    }
+    pub fn get_key(&self) -> TxKey {
+        match self.tx_type.creates_booking() {
+            true => TxKey{tx_id: self.tx_id, tx_type: None, dispute: None},
+            false => TxKey{tx_id: self.tx_id, tx_type: Some(self.tx_type), dispute: self.dispute},
+        }
+    }
+    pub fn get_fingerprint(&self) -> TxFingerprint {
+        TxFingerprint{
+            client_id: self.client_id,
+            tx_type: self.tx_type,
+            amount: self.amount,
+            currency: self.currency.clone(),
+            to_currency: self.to_currency.clone(),
+            to_client: self.to_client,
+            period: self.period.clone(),
+            status: self.status,
+            operator: self.operator.clone(),
+            reason: self.reason,
+            review_period: self.review_period,
+        }
+    }
+    // The way the funds move. A negative deposit takes funds out like a
+    // withdrawal and a negative withdrawal brings them in like a deposit.
+    pub fn get_direction(&self) -> TxType {
+        match (self.tx_type, self.amount.is_some_and(|a| a.is_negative())) {
+            (TxType::Deposit, true) => TxType::Withdrawal,
+            (TxType::Withdrawal, true) => TxType::Deposit,
+            (tx_type, _) => tx_type,
+        }
+    }
+    pub fn get_metadata(&self) -> TxMetadata {
+        TxMetadata{
+            timestamp: self.timestamp,
+            reference: self.reference.clone(),
+            memo: self.memo.clone(),
+            batch: self.batch.clone(),
+        }
+    }
+    // Parses the amount read from the input, truncating the decimal places
+    // beyond what an amount keeps or refusing them.
+    pub fn parse_amount(&mut self, truncate: bool) -> LedgerResult<()> {
+        if let Some(raw) = self.raw_amount.take() {
+            self.amount = Some(match truncate {
+                true => Amount::parse_truncated(&raw)?,
+                false => raw.parse()?,
+            });
+        }
+
+        Ok(())
+    }
+    // Amount as submitted, before or after it was parsed.
+    pub fn get_submitted_amount(&self) -> Option<String> {
+        self.amount.map(|a| a.to_string()).or_else(|| self.raw_amount.clone())
+    }
+    // Currency the transaction is booked in, the default one if not given.
+    pub fn get_currency(&self) -> Currency {
+        self.currency.clone().unwrap_or_default()
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::dom::{Tx, TxType};
+    use crate::dom::{AccountStatus, Currency, LedgerErrorKind, ReasonCode, Tx, TxType};
 
     #[test]
     fn deserialize_tx_csv() {
         let cases: Vec<(&str, Tx)> = vec![
             ("type,client,tx,amount
-deposit,1,1,1.0004", Tx{tx_id: 1, client_id: 1, tx_type: TxType::Deposit, amount: Some(1_0004.into())}),
+deposit,1,1,1.0004", Tx::new(1, 1, TxType::Deposit, Some(1_0004.into()))),
 ("type, client, tx, amount
-dispute,    1,  1", Tx{tx_id: 1, client_id: 1, tx_type: TxType::Dispute, amount: None}),
+dispute,    1,  1", Tx::new(1, 1, TxType::Dispute, None)),
+("type, client, tx, amount, currency
+deposit,    1,  1,    2.5,      USD", Tx{currency: Some(Currency::new("USD")), ..Tx::new(1, 1, TxType::Deposit, Some(2_5000.into()))}),
+("type, client, tx, amount, currency
+withdrawal, 1,  2,    1.0", Tx::new(2, 1, TxType::Withdrawal, Some(1_0000.into()))),
+("type, client, tx, amount, currency, to_currency
+convert,    1,  3,    1.0,  EUR,      USD", Tx{currency: Some(Currency::new("EUR")), to_currency: Some(Currency::new("USD")), ..Tx::new(3, 1, TxType::Convert, Some(1_0000.into()))}),
+("type, client, tx, amount, to_client
+transfer,   1,  4,    1.0,  2", Tx{to_client: Some(2), ..Tx::new(4, 1, TxType::Transfer, Some(1_0000.into()))}),
+("type, client, tx, amount, operator, reason, review_period
+unlock,     1,  5,        , alice,    fraud_cleared, 10", Tx{operator: Some("alice".into()), reason: Some(ReasonCode::FraudCleared), review_period: Some(10), ..Tx::new(5, 1, TxType::Unlock, None)}),
+("type, client, tx, amount, operator, reason, status
+set_status, 1,  5,        , alice,    customer_request, withdrawals_frozen", Tx{operator: Some("alice".into()), reason: Some(ReasonCode::CustomerRequest), status: Some(AccountStatus::WithdrawalsFrozen), ..Tx::new(5, 1, TxType::SetStatus, None)}),
+("type, client, tx, amount, reference, memo
+adjustment_debit, 1, 6, 2.5, CASE-12,\"double deposit, see tx 1\"", Tx{reference: Some("CASE-12".into()), memo: Some("double deposit, see tx 1".into()), ..Tx::new(6, 1, TxType::AdjustmentDebit, Some(2_5000.into()))}),
+("type, client, tx, amount, dispute
+dispute,    1,  1,    0.5,    2", Tx{dispute: Some(2), ..Tx::new(1, 1, TxType::Dispute, Some(5000.into()))}),
+("type, client, tx, amount, timestamp, reference, memo
+deposit,    1,  7,    1.0, 1700000000, PSP-991,   card top up", Tx{timestamp: Some(1_700_000_000), reference: Some("PSP-991".into()), memo: Some("card top up".into()), ..Tx::new(7, 1, TxType::Deposit, Some(1_0000.into()))}),
+("type, client, tx, amount, timestamp, reference, memo
+deposit,    1,  8,    1.0,          ,          ,", Tx::new(8, 1, TxType::Deposit, Some(1_0000.into()))),
         ];
 
         for (case, expected) in cases.iter() {
@@ -43,24 +276,62 @@ dispute,    1,  1", Tx{tx_id: 1, client_id: 1, tx_type: TxType::Dispute, amount:
                 .trim(csv::Trim::All)
                 .from_reader(case.as_bytes());
             for record in rdr.deserialize() {
-                let r: Tx = record.map_err(|e| println!("{}", e)).unwrap();
+                let mut r: Tx = record.map_err(|e| println!("{}", e)).unwrap();
+                r.parse_amount(false).unwrap();
                 assert_eq!(expected.amount, r.amount);
                 assert_eq!(expected.tx_type, r.tx_type);
                 assert_eq!(expected.tx_id, r.tx_id);
                 assert_eq!(expected.client_id, r.client_id);
+                assert_eq!(expected.currency, r.currency);
+                assert_eq!(expected.to_currency, r.to_currency);
+                assert_eq!(expected.to_client, r.to_client);
+                assert_eq!(expected.operator, r.operator);
+                assert_eq!(expected.reason, r.reason);
+                assert_eq!(expected.review_period, r.review_period);
+                assert_eq!(expected.status, r.status);
+                assert_eq!(expected.timestamp, r.timestamp);
+                assert_eq!(expected.reference, r.reference);
+                assert_eq!(expected.memo, r.memo);
+                assert_eq!(expected.dispute, r.dispute);
             }
         }
     }
     
+    #[test]
+    fn excess_precision_csv() {
+        let data = "type, client, tx, amount
+deposit,    1,  1,    1.00005
+deposit,    1,  2,    -2.123456
+deposit,    1,  3,    1.0000x";
+        let rows = || csv::ReaderBuilder::new()
+            .has_headers(true)
+            .trim(csv::Trim::All)
+            .from_reader(data.as_bytes())
+            .into_deserialize::<Tx>()
+            .map(|r| r.unwrap())
+            .collect::<Vec<_>>();
+
+        let parsed: Vec<Option<i64>> = rows().into_iter()
+            .map(|mut tx| tx.parse_amount(true).ok().and(tx.amount).map(|a| a.to_i64()))
+            .collect();
+        assert_eq!(vec![Some(1_0000), Some(-2_1234), None], parsed);
+
+        for mut tx in rows() {
+            let err = tx.parse_amount(false).unwrap_err();
+            assert!(matches!(err.kind(), LedgerErrorKind::ExcessPrecision(_) | LedgerErrorKind::MalformedAmount(_)), "{}", err);
+            assert_eq!(None, tx.amount);
+        }
+    }
+
     #[test]
     #[ignore]
     fn serialize_tx_csv() {
         let cases: Vec<(&str, Tx)> = vec![
             ("type,client,tx,amount
 deposit,1,1,1.0004
-", Tx{tx_id: 1, client_id: 1, tx_type: TxType::Deposit, amount: Some(1_0004.into())}),
+", Tx::new(1, 1, TxType::Deposit, Some(1_0004.into()))),
 ("type,client,tx,amount
-dispute,1,1", Tx{tx_id: 1, client_id: 1, tx_type: TxType::Dispute, amount: None}),
+dispute,1,1", Tx::new(1, 1, TxType::Dispute, None)),
         ];
 
         for (expected, case) in cases.iter() {
@@ -3,98 +3,432 @@ use std::collections::HashMap;
 use async_trait::async_trait;
 use futures::lock::Mutex;
 
-use crate::{app::{AccountRepository}, dom::{LedgerResult, Account, LedgerError, AccountSummary}};
+use crate::{app::{AccountRepository}, dom::{LedgerResult, Account, Amount, Conversion, Currency, LedgerError, AccountSpec, AccountStatus, AccountSummary, StatusChange, TxType, Unlock}};
 
 #[derive(Default)]
 pub struct InMemoryAccountRepository {
-    accounts: Mutex<HashMap<u16, Account>>
+    accounts: Mutex<HashMap<u16, Account>>,
+    // Original of every account the batch in progress changed, none for
+    // those it created.
+    checkpoint: Mutex<Option<Checkpoint>>,
+}
+
+#[derive(Default)]
+struct Checkpoint {
+    accounts: HashMap<u16, Option<Account>>,
 }
 
 impl InMemoryAccountRepository {
     pub fn new() -> Self {
         InMemoryAccountRepository::default()
     }
-    async fn get_account(&self, client_id: u16) -> LedgerResult<Account> {
-        let a = *self.accounts.lock().await.get(&client_id)
-            .ok_or_else(|| LedgerError::doesnt_exist("account does not exist"))?;
-
-        Ok(a)
-    }
     async fn update_account(&mut self, client_id: u16, account: Account) -> LedgerResult<()> {
-        self.accounts.lock().await.insert(client_id, account);
+        let mut store = self.accounts.lock().await;
+        self.store_account(&mut store, client_id, account).await;
 
         Ok(())
     }
+    // Stores an account, keeping the original for the batch in progress.
+    async fn store_account(&self, store: &mut HashMap<u16, Account>, client_id: u16, account: Account) {
+        if let Some(checkpoint) = self.checkpoint.lock().await.as_mut() {
+            checkpoint.accounts.entry(client_id).or_insert_with(|| store.get(&client_id).cloned());
+        }
+        store.insert(client_id, account);
+    }
+}
+
+// Accounts set up from a configuration file before any transaction.
+impl TryFrom<Vec<AccountSpec>> for InMemoryAccountRepository {
+    type Error = LedgerError;
+
+    fn try_from(specs: Vec<AccountSpec>) -> LedgerResult<Self> {
+        let mut accounts = HashMap::new();
+        for spec in specs {
+            let a = accounts.entry(spec.client)
+                .or_insert_with(|| Account::new(spec.client));
+            if spec.credit_limit != Amount::ZERO {
+                a.set_credit_limit(&spec.currency, spec.credit_limit)?;
+            }
+            if let Some(tier) = spec.tier {
+                a.set_tier(tier);
+            }
+        }
+
+        Ok(InMemoryAccountRepository{
+            accounts: Mutex::new(accounts),
+            ..InMemoryAccountRepository::default()
+        })
+    }
 }
 
 #[async_trait]
 impl AccountRepository for InMemoryAccountRepository {
+    async fn get_account(&self, client_id: u16) -> LedgerResult<Account>{
+        let a = self.accounts.lock().await.get(&client_id)
+            .cloned()
+            .ok_or_else(|| LedgerError::doesnt_exist("account"))?;
+
+        Ok(a)
+    }
     async fn get_or_create_account(&mut self, client_id: u16) -> LedgerResult<Account>{
         let mut store = self.accounts.lock().await;
         let a = match store.get(&client_id) {
-            Some(a) => *a,
+            Some(a) => a.clone(),
             None => {
                 let a = Account::new(client_id);
-                store.insert(client_id, a);
+                self.store_account(&mut store, client_id, a.clone()).await;
                 a
             },
         };
 
         Ok(a)
     }
-    async fn hold(&mut self, client_id: u16, amount: i64) -> LedgerResult<()>{
+    async fn open_account(&mut self, client_id: u16) -> LedgerResult<()>{
+        let mut store = self.accounts.lock().await;
+        if store.contains_key(&client_id) {
+            return account_err("account already exists");
+        }
+
+        self.store_account(&mut store, client_id, Account::new(client_id)).await;
+        Ok(())
+    }
+    // Closed accounts are kept, so that they still show up in reports.
+    async fn close_account(&mut self, client_id: u16) -> LedgerResult<()>{
+        let mut a = self.get_account(client_id).await?;
+        if a.is_locked() {
+            return account_err("account is locked");
+        }
+
+        a.close()?;
+        self.update_account(client_id, a).await
+    }
+    async fn hold(&mut self, client_id: u16, currency: &Currency, amount: Amount) -> LedgerResult<()>{
+        let mut a = self.get_account(client_id).await?;
+        if a.is_locked() {
+            return account_err("account is locked");
+        }
+
+        a.hold(currency, amount)?;
+        self.update_account(client_id, a).await
+    }
+    async fn release(&mut self, client_id: u16, currency: &Currency, amount: Amount) -> LedgerResult<()>{
+        let mut a = self.get_account(client_id).await?;
+        if a.is_locked() {
+            return account_err("account is locked");
+        }
+
+        a.release(currency, amount)?;
+        self.update_account(client_id, a).await
+    }
+    async fn hold_claim(&mut self, client_id: u16, currency: &Currency, amount: Amount) -> LedgerResult<()>{
+        let mut a = self.get_account(client_id).await?;
+        if a.is_locked() {
+            return account_err("account is locked");
+        }
+
+        a.hold_claim(currency, amount)?;
+        self.update_account(client_id, a).await
+    }
+    async fn drop_claim(&mut self, client_id: u16, currency: &Currency, amount: Amount) -> LedgerResult<()>{
+        let mut a = self.get_account(client_id).await?;
+        if a.is_locked() {
+            return account_err("account is locked");
+        }
+
+        a.drop_claim(currency, amount)?;
+        self.update_account(client_id, a).await
+    }
+    // A claim that has been paid out moves from held to available, which is
+    // the same as releasing held funds.
+    async fn pay_claim(&mut self, client_id: u16, currency: &Currency, amount: Amount) -> LedgerResult<()>{
+        self.release(client_id, currency, amount).await
+    }
+    async fn authorize(&mut self, client_id: u16, currency: &Currency, amount: Amount) -> LedgerResult<()>{
+        let mut a = self.get_account(client_id).await?;
+        if a.is_locked() {
+            return account_err("account is locked");
+        }
+
+        if amount > a.get_headroom(currency)? {
+            return account_err("insufficient funds");
+        }
+
+        a.authorize(currency, amount)?;
+        self.update_account(client_id, a).await
+    }
+    async fn capture(&mut self, client_id: u16, currency: &Currency, authorized: Amount, captured: Amount) -> LedgerResult<()>{
         let mut a = self.get_account(client_id).await?;
         if a.is_locked() {
             return account_err("account is locked");
         }
 
-        a.hold(amount);
+        a.capture(currency, authorized, captured)?;
         self.update_account(client_id, a).await
     }
-    async fn release(&mut self, client_id: u16, amount: i64) -> LedgerResult<()>{
+    // Voiding only gives the reserved funds back, so it's allowed on locked
+    // and frozen accounts as well, see `TxType::concludes_reserved`. Expired
+    // authorizations are voided this way.
+    async fn void(&mut self, client_id: u16, currency: &Currency, amount: Amount) -> LedgerResult<()>{
+        let mut a = self.get_account(client_id).await?;
+
+        a.void(currency, amount)?;
+        self.update_account(client_id, a).await
+    }
+    async fn deposit_pending(&mut self, client_id: u16, currency: &Currency, amount: Amount) -> LedgerResult<()>{
         let mut a = self.get_account(client_id).await?;
         if a.is_locked() {
             return account_err("account is locked");
         }
 
-        a.release(amount);
+        a.deposit_pending(currency, amount)?;
         self.update_account(client_id, a).await
     }
-    async fn deposit(&mut self, client_id: u16, amount: i64) -> LedgerResult<()>{
+    // Settling and rejecting only act on funds that are already pending, so
+    // like voiding they are allowed on locked and frozen accounts as well.
+    async fn settle(&mut self, client_id: u16, currency: &Currency, amount: Amount) -> LedgerResult<()>{
+        let mut a = self.get_account(client_id).await?;
+
+        a.settle(currency, amount)?;
+        self.update_account(client_id, a).await
+    }
+    async fn reject_settlement(&mut self, client_id: u16, currency: &Currency, amount: Amount) -> LedgerResult<()>{
+        let mut a = self.get_account(client_id).await?;
+
+        a.reject_settlement(currency, amount)?;
+        self.update_account(client_id, a).await
+    }
+    async fn deposit(&mut self, client_id: u16, currency: &Currency, amount: Amount) -> LedgerResult<()>{
         let mut a = self.get_account(client_id).await?;
         if a.is_locked() {
             return account_err("account is locked");
         }
 
-        a.deposit(amount);
+        a.deposit(currency, amount)?;
         self.update_account(client_id, a).await
     }
-    async fn withdraw(&mut self, client_id: u16, amount: i64) -> LedgerResult<()>{
+    async fn withdraw(&mut self, client_id: u16, currency: &Currency, amount: Amount) -> LedgerResult<()>{
         let mut a = self.get_account(client_id).await?;
         if a.is_locked() {
             return account_err("account is locked");
         }
 
-        if amount > a.get_available() {
+        if amount > a.get_headroom(currency)? {
             return account_err("insufficient funds");
         }
 
-        a.withdraw(amount);
+        a.withdraw(currency, amount)?;
         self.update_account(client_id, a).await
     }
-    async fn withdraw_and_lock(&mut self, client_id: u16, amount: i64) -> LedgerResult<()>{
+    // Same as withdraw but the balance is allowed to go negative.
+    async fn debit(&mut self, client_id: u16, currency: &Currency, amount: Amount) -> LedgerResult<()>{
+        let mut a = self.get_account(client_id).await?;
+        if a.is_locked() {
+            return account_err("account is locked");
+        }
+
+        a.withdraw(currency, amount)?;
+        self.update_account(client_id, a).await
+    }
+    async fn withdraw_and_lock(&mut self, client_id: u16, currency: &Currency, amount: Amount) -> LedgerResult<()>{
         let mut a = self.get_account(client_id).await?;
         if a.is_locked() {
             return account_err("account is locked");
         }
 
         // *Assuming* that chargeback can make the account negative.
-        a.withdraw_and_lock(amount);
+        a.withdraw_and_lock(currency, amount)?;
+        self.update_account(client_id, a).await
+    }
+    // The account is most likely locked by the chargeback being reversed.
+    async fn reverse_chargeback(&mut self, client_id: u16, currency: &Currency, amount: Amount, unlock: bool) -> LedgerResult<()>{
+        let mut a = self.get_account(client_id).await?;
+
+        a.reverse_chargeback(currency, amount, unlock)?;
+        self.update_account(client_id, a).await
+    }
+    async fn transfer(&mut self, from_client_id: u16, to_client_id: u16, currency: &Currency, amount: Amount) -> LedgerResult<()>{
+        if from_client_id == to_client_id {
+            return account_err("can't transfer to the same account");
+        }
+
+        // The receiver is only stored once the transfer goes through, so a
+        // refused transfer doesn't leave an empty account behind.
+        let mut from = self.get_account(from_client_id).await?;
+        let mut to = self.accounts.lock().await.get(&to_client_id)
+            .cloned()
+            .unwrap_or_else(|| Account::new(to_client_id));
+        if from.is_locked() || to.is_locked() {
+            return account_err("account is locked");
+        }
+        if !to.get_status().allows(TxType::Deposit) {
+            return account_err("account doesn't accept deposits");
+        }
+
+        if amount > from.get_headroom(currency)? {
+            return account_err("insufficient funds");
+        }
+
+        // Both legs are applied to copies first, so either both accounts are
+        // updated or none of them is.
+        from.withdraw(currency, amount)?;
+        to.deposit(currency, amount)?;
+        self.update_account(from_client_id, from).await?;
+        self.update_account(to_client_id, to).await
+    }
+    // Takes disputed transfer funds back from the receiver, which gets locked
+    // the same way as after a chargeback, and returns them to the sender.
+    async fn reverse_transfer(&mut self, from_client_id: u16, to_client_id: u16, currency: &Currency, amount: Amount) -> LedgerResult<()>{
+        let mut from = self.get_account(from_client_id).await?;
+        let mut to = self.get_account(to_client_id).await?;
+        if from.is_locked() || to.is_locked() {
+            return account_err("account is locked");
+        }
+
+        from.withdraw_and_lock(currency, amount)?;
+        to.deposit(currency, amount)?;
+        self.update_account(from_client_id, from).await?;
+        self.update_account(to_client_id, to).await
+    }
+    // Sends the charged back funds of a transfer to the receiver again, the
+    // receiver is most likely locked by the chargeback being reversed.
+    async fn reverse_transfer_chargeback(&mut self, from_client_id: u16, to_client_id: u16, currency: &Currency, amount: Amount, unlock: bool) -> LedgerResult<()>{
+        let mut from = self.get_account(from_client_id).await?;
+        let mut to = self.get_account(to_client_id).await?;
+        if from.is_locked() {
+            return account_err("account is locked");
+        }
+
+        if amount > from.get_headroom(currency)? {
+            return account_err("insufficient funds");
+        }
+
+        from.withdraw(currency, amount)?;
+        to.reverse_chargeback(currency, amount, unlock)?;
+        self.update_account(from_client_id, from).await?;
+        self.update_account(to_client_id, to).await
+    }
+    async fn convert(&mut self, client_id: u16, conversion: &Conversion) -> LedgerResult<()>{
+        let mut a = self.get_account(client_id).await?;
+        if a.is_locked() {
+            return account_err("account is locked");
+        }
+
+        if conversion.amount > a.get_headroom(&conversion.from)? {
+            return account_err("insufficient funds");
+        }
+
+        a.convert(conversion)?;
         self.update_account(client_id, a).await
     }
+    async fn unlock(&mut self, unlock: Unlock) -> LedgerResult<()>{
+        let mut a = self.get_account(unlock.client).await?;
+        match a.get_status() {
+            AccountStatus::Active => return account_err("account is not locked"),
+            AccountStatus::Closed => return account_err("account is closed"),
+            _ => {},
+        }
+
+        a.unlock(unlock);
+        self.update_account(a.get_client_id(), a).await
+    }
+    // Restrictions only add up, a frozen account doesn't become less frozen.
+    async fn restrict_account(&mut self, client_id: u16, status: AccountStatus) -> LedgerResult<()>{
+        let mut a = self.get_account(client_id).await?;
+
+        a.restrict(status);
+        self.update_account(client_id, a).await
+    }
+    async fn change_status(&mut self, change: StatusChange) -> LedgerResult<()>{
+        let mut a = self.get_or_create_account(change.client).await?;
+        if a.get_status() != change.from {
+            return account_err("account status has changed");
+        }
+
+        a.change_status(change);
+        self.update_account(a.get_client_id(), a).await
+    }
+    // Credit limits are set by operators, locked accounts included.
+    async fn set_credit_limit(&mut self, client_id: u16, currency: &Currency, limit: Amount) -> LedgerResult<()>{
+        let mut a = self.get_or_create_account(client_id).await?;
+
+        a.set_credit_limit(currency, limit)?;
+        self.update_account(client_id, a).await
+    }
+    // Fees are charged on locked accounts too, a chargeback fee is due after
+    // the chargeback has locked the account.
+    async fn charge_fee(&mut self, client_id: u16, currency: &Currency, amount: Amount) -> LedgerResult<()>{
+        let mut a = self.get_account(client_id).await?;
+
+        a.charge_fee(currency, amount)?;
+        self.update_account(client_id, a).await
+    }
+    // Interest is credited to the client, the house side of it is in the
+    // journal.
+    async fn pay_interest(&mut self, client_id: u16, currency: &Currency, amount: Amount) -> LedgerResult<()>{
+        let mut a = self.get_account(client_id).await?;
+        if a.is_locked() {
+            return account_err("account is locked");
+        }
+
+        a.deposit(currency, amount)?;
+        self.update_account(client_id, a).await
+    }
+    async fn begin_batch(&mut self) -> LedgerResult<()>{
+        let mut checkpoint = self.checkpoint.lock().await;
+        if checkpoint.is_some() {
+            return account_err("batch already in progress");
+        }
+
+        *checkpoint = Some(Checkpoint::default());
+        Ok(())
+    }
+    async fn commit_batch(&mut self) -> LedgerResult<()>{
+        match self.checkpoint.lock().await.take() {
+            Some(_) => Ok(()),
+            None => account_err("no batch in progress"),
+        }
+    }
+    async fn rollback_batch(&mut self) -> LedgerResult<()>{
+        let checkpoint = match self.checkpoint.lock().await.take() {
+            Some(c) => c,
+            None => return account_err("no batch in progress"),
+        };
+
+        let mut store = self.accounts.lock().await;
+        for (client_id, original) in checkpoint.accounts {
+            match original {
+                Some(a) => store.insert(client_id, a),
+                None => store.remove(&client_id),
+            };
+        }
+        Ok(())
+    }
     async fn dump_accounts(&self) -> LedgerResult<Vec<AccountSummary>>{
         let store = self.accounts.lock().await;
-        return Ok(store.values().map(AccountSummary::from).collect());
+        let mut summaries = Vec::new();
+        for a in store.values() {
+            summaries.extend(a.summaries()?);
+        }
+
+        Ok(summaries)
+    }
+    async fn dump_unlocks(&self) -> LedgerResult<Vec<Unlock>>{
+        let store = self.accounts.lock().await;
+        let mut unlocks: Vec<Unlock> = store.values()
+            .flat_map(|a| a.get_unlocks().iter().cloned())
+            .collect();
+        unlocks.sort_by_key(|u| u.seq);
+
+        Ok(unlocks)
+    }
+    async fn dump_status_changes(&self) -> LedgerResult<Vec<StatusChange>>{
+        let store = self.accounts.lock().await;
+        let mut changes: Vec<StatusChange> = store.values()
+            .flat_map(|a| a.get_status_changes().iter().cloned())
+            .collect();
+        changes.sort_by_key(|c| c.seq);
+
+        Ok(changes)
     }
 }
 
@@ -1,16 +1,83 @@
 use crate::dom::LedgerResult;
-use std::{sync::Arc, collections::HashMap};
+use std::{sync::Arc, collections::{BTreeMap, BTreeSet, HashMap, HashSet}};
 
 use async_trait::async_trait;
 use futures::lock::Mutex;
 
-use crate::{app::{AccountRepository}, dom::{TxType, Tx, LedgerError, BookingState}};
+use crate::{app::{AccountRepository}, dom::{TxType, Tx, LedgerError, BookingState, DisputeState, Currencies, RateTable, FeeSchedule, HistoryEntry, Amount, Unlock, LedgerPolicy, Adjustment, DisputeSummary, InterestTable, Accrual, Account, Currency, TxMetadata, TxKey, TxFingerprint, RoundingMode, LimitTable, check_period, LimitKind, LimitViolation, WithdrawalRecord, RiskRules, RiskActivity, RiskAction, RiskEvent, AccountStatus, StatusChange, Journal, JournalAccount, JournalSide, JournalEntry, TrialBalance, BookingKind, Conversion, HouseSummary}};
 use crate::dom::Booking;
 use crate::app::BookingRepository;
 
 pub struct InMemoryBookingRepository {
     account_repo: Arc<Mutex<dyn AccountRepository>>,
     bookings: Mutex<HashMap<u32, Booking>>,
+    // Fees by the key of the transaction they were charged for. Disputes
+    // only refer to the bookings above, so a fee can't be disputed.
+    fee_bookings: Mutex<HashMap<TxKey, Booking>>,
+    // Interest paid by the accrual runs, in the order it was paid.
+    interest_bookings: Mutex<Vec<Booking>>,
+    currencies: Currencies,
+    rates: RateTable,
+    fees: FeeSchedule,
+    interest: InterestTable,
+    limits: LimitTable,
+    risk_rules: RiskRules,
+    history: Mutex<Vec<HistoryEntry>>,
+    adjustments: Mutex<Vec<Adjustment>>,
+    accruals: Mutex<Vec<Accrual>>,
+    // Fingerprints of the accepted transactions, to tell replays from
+    // conflicting reuses of a tx id.
+    fingerprints: Mutex<HashMap<TxKey, TxFingerprint>>,
+    // Client, currency and period of every accrual so far.
+    accrued: Mutex<HashSet<(u16, Currency, String)>>,
+    // Withdrawals of every client, to check the limits over a window.
+    withdrawals: Mutex<HashMap<u16, Vec<WithdrawalRecord>>>,
+    // Number of refused withdrawals per client, tier and limit. Refusals are
+    // counted even if they roll back a batch.
+    violations: Mutex<BTreeMap<(u16, String, LimitKind), u64>>,
+    // Activity of every client the risk rules are evaluated on, and the
+    // rules triggered so far.
+    activity: Mutex<HashMap<u16, RiskActivity>>,
+    risk_events: Mutex<Vec<RiskEvent>>,
+    // Balanced entries of every change to the client balances.
+    journal: Mutex<Journal>,
+    // Part of the FX residue below the amount scale per target currency,
+    // carried over to the next conversion.
+    fx_carry: Mutex<HashMap<Currency, i128>>,
+    policy: LedgerPolicy,
+    // Bookings that change by themselves after a deadline, such as expiring
+    // authorizations and pending deposits, as the sequence number of the
+    // deadline and the transaction id.
+    deadlines: Mutex<BTreeSet<(u64, u32)>>,
+    // Changes of the batch in progress, if any.
+    undo: Mutex<Option<UndoLog>>,
+    // Sequence number of the transaction being processed, starting from 1.
+    seq: u64,
+}
+
+// What a batch changed, to undo it if a leg fails. A transaction on its own
+// is a batch of one. Records that are only appended to are cut back to their
+// length before the batch, the rest keeps the original of whatever the batch
+// touched. The sequence number is left alone, so the legs of a failed batch
+// still take up their positions.
+#[derive(Default)]
+struct UndoLog {
+    bookings: HashMap<u32, Option<Booking>>,
+    fee_bookings: Vec<TxKey>,
+    interest_bookings: usize,
+    history: usize,
+    adjustments: usize,
+    accruals: usize,
+    risk_events: usize,
+    journal: usize,
+    fingerprints: Vec<TxKey>,
+    accrued: Vec<(u16, Currency, String)>,
+    // Client of every withdrawal recorded, in order.
+    withdrawals: Vec<u16>,
+    activity: HashMap<u16, Option<RiskActivity>>,
+    fx_carry: HashMap<Currency, Option<i128>>,
+    added_deadlines: Vec<(u64, u32)>,
+    passed_deadlines: Vec<(u64, u32)>,
 }
 
 impl InMemoryBookingRepository {
@@ -18,45 +85,643 @@ impl InMemoryBookingRepository {
         InMemoryBookingRepository{
             account_repo,
             bookings: Mutex::new(HashMap::default()),
+            fee_bookings: Mutex::new(HashMap::new()),
+            interest_bookings: Mutex::new(Vec::new()),
+            currencies: Currencies::default(),
+            rates: RateTable::default(),
+            fees: FeeSchedule::default(),
+            interest: InterestTable::default(),
+            limits: LimitTable::default(),
+            risk_rules: RiskRules::default(),
+            history: Mutex::new(Vec::new()),
+            adjustments: Mutex::new(Vec::new()),
+            accruals: Mutex::new(Vec::new()),
+            fingerprints: Mutex::new(HashMap::new()),
+            accrued: Mutex::new(HashSet::new()),
+            withdrawals: Mutex::new(HashMap::new()),
+            violations: Mutex::new(BTreeMap::new()),
+            activity: Mutex::new(HashMap::new()),
+            risk_events: Mutex::new(Vec::new()),
+            journal: Mutex::new(Journal::new()),
+            fx_carry: Mutex::new(HashMap::new()),
+            policy: LedgerPolicy::default(),
+            deadlines: Mutex::new(BTreeSet::new()),
+            undo: Mutex::new(None),
+            seq: 0,
         }
-    } 
-    async fn get_or_create_booking(&self, tx: Tx) -> LedgerResult<Booking> {
-        let mut store = self.bookings.lock().await;
+    }
+    pub fn with_currencies(mut self, currencies: Currencies) -> Self {
+        self.currencies = currencies;
+        self
+    }
+    pub fn with_rates(mut self, rates: RateTable) -> Self {
+        self.rates = rates;
+        self
+    }
+    pub fn with_fees(mut self, fees: FeeSchedule) -> Self {
+        self.fees = fees;
+        self
+    }
+    pub fn with_interest(mut self, interest: InterestTable) -> Self {
+        self.interest = interest;
+        self
+    }
+    pub fn with_limits(mut self, limits: LimitTable) -> Self {
+        self.limits = limits;
+        self
+    }
+    pub fn with_risk_rules(mut self, risk_rules: RiskRules) -> Self {
+        self.risk_rules = risk_rules;
+        self
+    }
+    pub fn with_policy(mut self, policy: LedgerPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+    // A new booking is only stored once its transaction has been applied, so
+    // a rejected transaction leaves nothing behind for its id.
+    async fn get_or_create_booking(&self, tx: &Tx) -> LedgerResult<Booking> {
+        let store = self.bookings.lock().await;
         match store.get(&tx.tx_id) {
-            Some(b) => Ok(*b),
+            Some(b) if tx.tx_type.creates_booking() => {
+                let currency = tx.get_currency();
+                let amount = tx.amount.map(|a| self.fit_amount(&currency, a)).transpose()?;
+                if b.get_client_id() != tx.client_id || b.get_tx_type() != tx.tx_type
+                    || Some(b.get_amount()) != amount || *b.get_currency() != currency {
+                    return Err(LedgerError::conflict(format!("tx {} is already used", tx.tx_id)));
+                }
+                Ok(b.clone())
+            },
+            Some(b) => Ok(b.clone()),
+            None if !tx.tx_type.creates_booking() => Err(LedgerError::doesnt_exist("booking")),
             None => {
-                // *Assuming* that negative amount is not allowed.
-                let amount = tx.amount.ok_or_else(|| booking_err("missing amount"))?.to_i64();
-                if amount < 0 {
+                // Negative amounts are refused unless the policy allows them,
+                // and then only on deposits and withdrawals, which don't
+                // touch the funds of anyone else.
+                let amount = tx.amount.ok_or_else(|| booking_err("missing amount"))?;
+                let negative_allowed = self.policy.allow_negative_amounts
+                    && matches!(tx.tx_type, TxType::Deposit | TxType::Withdrawal);
+                if amount.is_negative() && !negative_allowed {
                     return Err(booking_err("negative amount"));
                 }
-                let b = Booking::new(tx.tx_id, tx.client_id, amount);
-                store.insert(tx.tx_id, b);
+                let currency = tx.get_currency();
+                let amount = self.fit_amount(&currency, amount)?;
+                let mut b = Booking::new(tx.tx_id, tx.client_id, tx.tx_type, amount, currency);
+                b.set_metadata(tx.get_metadata());
+                if tx.tx_type == TxType::Transfer {
+                    b.set_counterparty(tx.to_client.ok_or_else(|| booking_err("missing destination client"))?);
+                }
                 Ok(b)
             },
         }
     }
+    // Amounts have to fit the scale of their currency, unless the policy
+    // truncates the excess decimal places.
+    fn fit_amount(&self, currency: &Currency, amount: Amount) -> LedgerResult<Amount> {
+        if self.policy.truncate_excess_precision {
+            return amount.round(self.currencies.get_scale(currency)?, RoundingMode::Down);
+        }
+
+        self.currencies.validate(currency, amount)?;
+        Ok(amount)
+    }
     async fn update_booking(&mut self, tx_id: u32, booking: Booking) -> LedgerResult<()> {
-        self.bookings.lock().await.insert(tx_id, booking);
+        let mut store = self.bookings.lock().await;
+        self.log(|undo| {
+            undo.bookings.entry(tx_id).or_insert_with(|| store.get(&tx_id).cloned());
+        }).await;
+        store.insert(tx_id, booking);
 
         Ok(())
     }
-}
+    // Records the amount a transaction moved, which for a partial dispute is
+    // less than the amount of the booking.
+    async fn record(&self, tx_type: TxType, booking: &Booking, amount: Amount, metadata: &TxMetadata) -> LedgerResult<()> {
+        self.post(tx_type, booking, amount).await?;
+        let metadata = metadata.clone();
+        self.history.lock().await.push(HistoryEntry{
+            seq: self.seq,
+            tx: booking.get_tx_id(),
+            client: booking.get_client_id(),
+            tx_type,
+            kind: booking.get_kind(),
+            amount,
+            currency: booking.get_currency().clone(),
+            timestamp: metadata.timestamp,
+            reference: metadata.reference,
+            memo: metadata.memo,
+            batch: metadata.batch,
+        });
+
+        Ok(())
+    }
+    // Posts the journal entries of a change to the client balances. Client
+    // funds come from and go to settlement cash, except for the funds moved
+    // between clients and those the house pays or charges itself. Moves
+    // within a client balance, such as holds and authorizations, post
+    // nothing. Conversions post their own entries.
+    async fn post(&self, tx_type: TxType, booking: &Booking, amount: Amount) -> LedgerResult<()> {
+        let client = JournalSide::client(booking.get_client_id());
+        let holder = JournalSide::client(booking.get_holder());
+        let cash = JournalSide::house(JournalAccount::SettlementCash);
+        let losses = JournalSide::house(JournalAccount::ChargebackLosses);
+        let withdrawal = booking.get_tx_type() == TxType::Withdrawal;
+        let transfer = booking.get_counterparty().is_some();
+
+        // Entries as the debited and the credited side.
+        let entries = match (booking.get_kind(), tx_type) {
+            (BookingKind::Fee, _) => vec![(client, JournalSide::house(JournalAccount::FeeIncome))],
+            (BookingKind::Interest, _) => vec![(JournalSide::house(JournalAccount::InterestExpense), client)],
+            (_, TxType::Deposit) => vec![(cash, client)],
+            (_, TxType::Withdrawal | TxType::Capture | TxType::RejectSettlement) => vec![(client, cash)],
+            (_, TxType::Transfer) => vec![(client, holder)],
+            (_, TxType::AdjustmentCredit) => vec![(JournalSide::house(JournalAccount::Adjustments), client)],
+            (_, TxType::AdjustmentDebit) => vec![(client, JournalSide::house(JournalAccount::Adjustments))],
+            // A disputed withdrawal is owed to the client until the claim
+            // is dropped or paid by the card network.
+            (_, TxType::Dispute) if withdrawal => vec![(losses, client)],
+            (_, TxType::Resolve) if withdrawal => vec![(client, losses)],
+            (_, TxType::Chargeback) if withdrawal => vec![(cash, losses)],
+            (_, TxType::ChargebackReversal) if withdrawal => vec![(client, cash)],
+            (_, TxType::Chargeback) if transfer => vec![(holder, client)],
+            (_, TxType::ChargebackReversal) if transfer => vec![(client, holder)],
+            // A chargeback is paid out of settlement cash and recovered from
+            // the client, even if that takes its balance negative.
+            (_, TxType::Chargeback) => vec![(losses, cash), (client, losses)],
+            (_, TxType::ChargebackReversal) => vec![(cash, losses), (losses, client)],
+            _ => vec![],
+        };
+
+        let mut journal = self.journal.lock().await;
+        for (debit, credit) in entries {
+            journal.post(self.seq, booking.get_tx_id(), booking.get_currency(), debit, credit, amount)?;
+        }
+
+        Ok(())
+    }
+    // A conversion moves the client funds through the exchange position of
+    // the house in both currencies, the rounding goes to the FX residue.
+    async fn post_conversion(&self, booking: &Booking, conversion: &Conversion) -> LedgerResult<()> {
+        let client = JournalSide::client(booking.get_client_id());
+        let exchange = JournalSide::house(JournalAccount::FxExchange);
+        let residue = JournalSide::house(JournalAccount::FxResidue);
+        let (seq, tx_id) = (self.seq, booking.get_tx_id());
+
+        let mut journal = self.journal.lock().await;
+        journal.post(seq, tx_id, &conversion.from, client, exchange, conversion.amount)?;
+        journal.post(seq, tx_id, &conversion.to, exchange, client, conversion.converted)?;
+        journal.post(seq, tx_id, &conversion.to, exchange, residue, conversion.residue)
+    }
+    // Unlocks a locked account on behalf of an operator.
+    async fn unlock(&mut self, tx: &Tx) -> LedgerResult<()> {
+        let operator = tx.operator.clone()
+            .filter(|o| !o.is_empty())
+            .ok_or_else(|| booking_err("missing operator"))?;
+        let reason = tx.reason.ok_or_else(|| booking_err("missing reason"))?;
+
+        self.account_repo.lock().await
+            .unlock(Unlock{
+                client: tx.client_id,
+                operator,
+                reason,
+                seq: self.seq,
+                review_until: tx.review_period.map(|p| self.seq.saturating_add(p)),
+                timestamp: tx.timestamp,
+                reference: tx.reference.clone(),
+                memo: tx.memo.clone(),
+            }).await
+    }
+    // Withdrawals, transfers and captures all take funds out of the account
+    // of the client, so they are held to the same limits.
+    fn new_outflow(&self, tx: &Tx, currency: &Currency, amount: Amount) -> WithdrawalRecord {
+        WithdrawalRecord{
+            seq: self.seq,
+            timestamp: tx.timestamp,
+            currency: currency.clone(),
+            amount,
+        }
+    }
+    async fn record_outflow(&self, client: u16, outflow: WithdrawalRecord) {
+        self.withdrawals.lock().await.entry(client).or_default().push(outflow);
+        self.log(|undo| undo.withdrawals.push(client)).await;
+    }
+    // Takes `amount` out of the account of the booking's client, as long as
+    // the account isn't under review and the withdrawal limits allow it.
+    async fn withdraw(&self, tx: &Tx, account: &Account, booking: &Booking, amount: Amount) -> LedgerResult<()> {
+        if account.is_under_review(self.seq) {
+            return wrapped_booking_err("account is under review");
+        }
+        let withdrawal = self.new_outflow(tx, booking.get_currency(), amount);
+        self.check_limits(account, &withdrawal).await?;
+        self.account_repo.lock().await
+            .withdraw(booking.get_client_id(), booking.get_currency(), amount).await?;
+        self.record_outflow(booking.get_client_id(), withdrawal).await;
+
+        Ok(())
+    }
+    // Checks a withdrawal against the limits of the client's tier. A refused
+    // withdrawal is counted as a violation of the limit it breaks.
+    async fn check_limits(&self, account: &Account, withdrawal: &WithdrawalRecord) -> LedgerResult<()> {
+        let client = account.get_client_id();
+        let past = self.withdrawals.lock().await.get(&client).cloned().unwrap_or_default();
+        let Some(limit) = self.limits.check(account.get_tier(), withdrawal, &past)? else {
+            return Ok(());
+        };
+
+        *self.violations.lock().await
+            .entry((client, account.get_tier().to_string(), limit))
+            .or_default() += 1;
+        Err(LedgerError::limit_exceeded(format!("{} of client {}", limit, client)))
+    }
+    // Evaluates the risk rules after a transaction has been applied. Every
+    // triggered rule is recorded as an event and its action is taken on the
+    // account of the client right away.
+    async fn assess_risk(&self, tx: &Tx) -> LedgerResult<()> {
+        if self.risk_rules.is_empty() {
+            return Ok(());
+        }
+
+        // Rules look at the way the funds move, a negative deposit is an
+        // outflow like a withdrawal.
+        let direction = tx.get_direction();
+        let amount = tx.amount.unwrap_or_default();
+        let amount = if amount.is_negative() { (-amount)? } else { amount };
+        let triggered: Vec<_> = {
+            let mut activity = self.activity.lock().await;
+            self.log(|undo| {
+                undo.activity.entry(tx.client_id).or_insert_with(|| activity.get(&tx.client_id).cloned());
+            }).await;
+            let a = activity.entry(tx.client_id).or_default();
+            a.record(self.seq, direction, amount, self.risk_rules.get_horizon());
+            self.risk_rules.evaluate(a, self.seq, direction).into_iter().cloned().collect()
+        };
+
+        for rule in triggered {
+            let mut account_repo = self.account_repo.lock().await;
+            match rule.action {
+                RiskAction::Flag => {},
+                RiskAction::Freeze => account_repo.restrict_account(tx.client_id, AccountStatus::WithdrawalsFrozen).await?,
+                RiskAction::Lock => account_repo.restrict_account(tx.client_id, AccountStatus::FullyFrozen).await?,
+            }
+            self.risk_events.lock().await.push(RiskEvent{
+                seq: self.seq,
+                tx: tx.tx_id,
+                client: tx.client_id,
+                rule: rule.id,
+                action: rule.action,
+                timestamp: tx.timestamp,
+            });
+        }
+
+        Ok(())
+    }
+    // Moves an account to another status on behalf of an operator.
+    async fn set_status(&mut self, tx: &Tx) -> LedgerResult<()> {
+        let operator = tx.operator.clone()
+            .filter(|o| !o.is_empty())
+            .ok_or_else(|| booking_err("missing operator"))?;
+        let reason = tx.reason.ok_or_else(|| booking_err("missing reason"))?;
+        let to = tx.status.ok_or_else(|| booking_err("missing status"))?;
+        if to == AccountStatus::Closed {
+            return wrapped_booking_err("accounts are closed by a close transaction");
+        }
+
+        let mut account_repo = self.account_repo.lock().await;
+        let from = account_repo.get_or_create_account(tx.client_id).await?.get_status();
+        account_repo
+            .change_status(StatusChange{
+                client: tx.client_id,
+                operator,
+                reason,
+                seq: self.seq,
+                from,
+                to,
+                timestamp: tx.timestamp,
+                reference: tx.reference.clone(),
+                memo: tx.memo.clone(),
+            }).await
+    }
+    // Sets the credit line of an account on behalf of an operator.
+    async fn set_credit_limit(&mut self, tx: &Tx) -> LedgerResult<()> {
+        tx.operator.as_ref()
+            .filter(|o| !o.is_empty())
+            .ok_or_else(|| booking_err("missing operator"))?;
+        tx.reason.ok_or_else(|| booking_err("missing reason"))?;
+        let currency = tx.get_currency();
+        let limit = self.fit_amount(&currency, tx.amount.ok_or_else(|| booking_err("missing amount"))?)?;
+
+        self.account_repo.lock().await
+            .set_credit_limit(tx.client_id, &currency, limit).await
+    }
+    // Applies the deadlines that passed before the current transaction:
+    // expired authorizations are voided and pending deposits are settled.
+    // A booking that has moved on in the meantime is left alone.
+    async fn apply_deadlines(&mut self) -> LedgerResult<()> {
+        let passed = {
+            let mut deadlines = self.deadlines.lock().await;
+            let upcoming = deadlines.split_off(&(self.seq, 0));
+            std::mem::replace(&mut *deadlines, upcoming)
+        };
+        self.log(|undo| undo.passed_deadlines.extend(passed.iter().copied())).await;
+
+        for (_, tx_id) in passed {
+            let Some(mut booking) = self.bookings.lock().await.get(&tx_id).cloned() else {
+                continue;
+            };
+
+            let (client_id, currency, amount) = (booking.get_client_id(), booking.get_currency(), booking.get_amount());
+            let tx_type = match booking.get_state() {
+                BookingState::Authorized => {
+                    self.account_repo.lock().await.void(client_id, currency, amount).await?;
+                    booking.set_state_and_lock(BookingState::Expired);
+                    TxType::Void
+                },
+                BookingState::Pending => {
+                    self.account_repo.lock().await.settle(client_id, currency, amount).await?;
+                    booking.set_state(BookingState::Normal);
+                    TxType::Settle
+                },
+                _ => continue,
+            };
+            self.update_booking(tx_id, booking.clone()).await?;
+            self.record(tx_type, &booking, amount, booking.get_metadata()).await?;
+        }
+
+        Ok(())
+    }
+    // Schedules a booking to change by itself `delay` transactions from now.
+    async fn add_deadline(&self, delay: u64, tx_id: u32) {
+        let deadline = (self.seq.saturating_add(delay), tx_id);
+        if self.deadlines.lock().await.insert(deadline) {
+            self.log(|undo| undo.added_deadlines.push(deadline)).await;
+        }
+    }
+    // Accrues interest on the available balances of every account, in the
+    // currency of the transaction or in every currency an account holds.
+    // Each accrual is a booking of its own funded by the interest expense
+    // house account. A period can only be accrued once, and accounts whose
+    // status doesn't take deposits are left out of the run.
+    async fn accrue(&mut self, tx: &Tx) -> LedgerResult<()> {
+        let period = tx.period.clone().unwrap_or_default();
+        check_period(&period)?;
+
+        let mut balances = self.account_repo.lock().await.dump_accounts().await?;
+        balances.retain(|b| b.status.allows(TxType::Accrue)
+            && tx.currency.iter().all(|c| *c == b.currency));
+        balances.sort_by(|a, b| (a.client, &a.currency).cmp(&(b.client, &b.currency)));
+
+        let mut accrued = self.accrued.lock().await;
+        if balances.iter().any(|b| accrued.contains(&(b.client, b.currency.clone(), period.clone()))) {
+            return wrapped_booking_err("period is already accrued");
+        }
+
+        for balance in balances {
+            let (client, currency) = (balance.client, balance.currency);
+            let interest = self.interest.get_interest(&currency, balance.available, self.currencies.get_scale(&currency)?)?;
+            if interest != Amount::ZERO {
+                self.account_repo.lock().await
+                    .pay_interest(client, &currency, interest).await?;
+                let booking = Booking::new_interest(tx.tx_id, client, interest, currency.clone());
+                self.record(TxType::Accrue, &booking, interest, &tx.get_metadata()).await?;
+                self.interest_bookings.lock().await.push(booking);
+                self.accruals.lock().await.push(Accrual{
+                    seq: self.seq,
+                    tx: tx.tx_id,
+                    client,
+                    period: period.clone(),
+                    currency: currency.clone(),
+                    balance: balance.available,
+                    interest,
+                });
+            }
+            let entry = (client, currency, period.clone());
+            if accrued.insert(entry.clone()) {
+                self.log(|undo| undo.accrued.push(entry)).await;
+            }
+        }
+
+        Ok(())
+    }
+    // Adjustments have to say where they come from.
+    fn new_adjustment(&self, tx: &Tx, booking: &Booking) -> LedgerResult<Adjustment> {
+        let non_empty = |f: &Option<String>| f.clone().filter(|v| !v.is_empty());
+
+        Ok(Adjustment{
+            seq: self.seq,
+            tx: booking.get_tx_id(),
+            client: booking.get_client_id(),
+            tx_type: tx.tx_type,
+            amount: booking.get_amount(),
+            currency: booking.get_currency().clone(),
+            timestamp: tx.timestamp,
+            reference: non_empty(&tx.reference).ok_or_else(|| booking_err("missing reference"))?,
+            memo: non_empty(&tx.memo).ok_or_else(|| booking_err("missing memo"))?,
+        })
+    }
+    // Charges the fee for a successfully applied transaction as a booking of
+    // its own, the fee is credited to the fee income house account. The fee
+    // of a dispute and its follow ups is paid by the client whose funds they
+    // move, which for a transfer is the receiver.
+    async fn charge_fee(&mut self, tx: &Tx, booking: &Booking, amount: Amount) -> LedgerResult<()> {
+        let currency = booking.get_currency();
+        let scale = self.currencies.get_scale(currency)?;
+        let fee = self.fees.get_fee(tx.tx_type, currency, amount, scale)?;
+        if fee == Amount::ZERO {
+            return Ok(());
+        }
+
+        let payer = match tx.tx_type {
+            TxType::Dispute | TxType::Resolve | TxType::Chargeback | TxType::Representment | TxType::ChargebackReversal => booking.get_holder(),
+            _ => booking.get_client_id(),
+        };
+        let fee_booking = Booking::new_fee(booking.get_tx_id(), payer, tx.tx_type, fee, currency.clone());
+        self.account_repo.lock().await
+            .charge_fee(payer, currency, fee).await?;
+        self.record(tx.tx_type, &fee_booking, fee, &tx.get_metadata()).await?;
+
+        let key = tx.get_key();
+        self.fee_bookings.lock().await.insert(key, fee_booking);
+        self.log(|undo| undo.fee_bookings.push(key)).await;
+
+        Ok(())
+    }
+    // Notes a change on the undo log of the batch in progress, if any.
+    async fn log(&self, change: impl FnOnce(&mut UndoLog)) {
+        if let Some(undo) = self.undo.lock().await.as_mut() {
+            change(undo);
+        }
+    }
+    async fn begin_batch(&self) {
+        *self.undo.lock().await = Some(UndoLog{
+            interest_bookings: self.interest_bookings.lock().await.len(),
+            history: self.history.lock().await.len(),
+            adjustments: self.adjustments.lock().await.len(),
+            accruals: self.accruals.lock().await.len(),
+            risk_events: self.risk_events.lock().await.len(),
+            journal: self.journal.lock().await.len(),
+            ..UndoLog::default()
+        });
+    }
+    async fn commit_batch(&self) {
+        *self.undo.lock().await = None;
+    }
+    async fn rollback_batch(&self) {
+        let Some(undo) = self.undo.lock().await.take() else {
+            return;
+        };
+
+        let mut bookings = self.bookings.lock().await;
+        for (tx_id, original) in undo.bookings {
+            match original {
+                Some(booking) => bookings.insert(tx_id, booking),
+                None => bookings.remove(&tx_id),
+            };
+        }
+        let mut fee_bookings = self.fee_bookings.lock().await;
+        for key in undo.fee_bookings {
+            fee_bookings.remove(&key);
+        }
+        self.interest_bookings.lock().await.truncate(undo.interest_bookings);
+        self.history.lock().await.truncate(undo.history);
+        self.adjustments.lock().await.truncate(undo.adjustments);
+        self.accruals.lock().await.truncate(undo.accruals);
+        self.risk_events.lock().await.truncate(undo.risk_events);
+        self.journal.lock().await.truncate(undo.journal);
+
+        let mut fingerprints = self.fingerprints.lock().await;
+        for key in undo.fingerprints {
+            fingerprints.remove(&key);
+        }
+        let mut accrued = self.accrued.lock().await;
+        for entry in undo.accrued {
+            accrued.remove(&entry);
+        }
+        let mut withdrawals = self.withdrawals.lock().await;
+        for client in undo.withdrawals.into_iter().rev() {
+            if let Some(past) = withdrawals.get_mut(&client) {
+                past.pop();
+                if past.is_empty() {
+                    withdrawals.remove(&client);
+                }
+            }
+        }
+        let mut activity = self.activity.lock().await;
+        for (client, original) in undo.activity {
+            match original {
+                Some(a) => activity.insert(client, a),
+                None => activity.remove(&client),
+            };
+        }
+        let mut fx_carry = self.fx_carry.lock().await;
+        for (currency, original) in undo.fx_carry {
+            match original {
+                Some(carry) => fx_carry.insert(currency, carry),
+                None => fx_carry.remove(&currency),
+            };
+        }
+        let mut deadlines = self.deadlines.lock().await;
+        for deadline in undo.added_deadlines {
+            deadlines.remove(&deadline);
+        }
+        deadlines.extend(undo.passed_deadlines);
+    }
+    // Processes a transaction on its own or as a leg of a batch, the caller
+    // keeps or undoes its changes.
+    async fn process(&mut self, mut tx: Tx) -> LedgerResult<()> {
+        tx.parse_amount(self.policy.truncate_excess_precision)?;
+
+        // An exact replay of an accepted transaction is acknowledged without
+        // applying it again, a different transaction under the same key is
+        // a conflict. Neither moves the clock.
+        let (key, fingerprint) = (tx.get_key(), tx.get_fingerprint());
+        match self.fingerprints.lock().await.get(&key) {
+            Some(accepted) if *accepted == fingerprint => return Ok(()),
+            Some(_) => return Err(LedgerError::conflict(format!("tx {} is already used", tx.tx_id))),
+            None => {},
+        }
+
+        self.seq += 1;
+        self.apply_deadlines().await?;
+
+        self.apply_tx(tx.clone()).await?;
+        self.fingerprints.lock().await.insert(key, fingerprint);
+        self.log(|undo| undo.fingerprints.push(key)).await;
+        self.assess_risk(&tx).await?;
+
+        Ok(())
+    }
+    // Keeps the changes of the batch in progress if `result` is ok, undoes
+    // them on both repositories otherwise.
+    async fn end_batch(&self, result: LedgerResult<()>) -> LedgerResult<()> {
+        if let Err(e) = result {
+            self.account_repo.lock().await.rollback_batch().await?;
+            self.rollback_batch().await;
+            return Err(e);
+        }
+
+        self.commit_batch().await;
+        self.account_repo.lock().await.commit_batch().await
+    }
+    // Under strict accounts, only accounts that have been opened take part
+    // in transactions.
+    async fn check_account(&self, client_id: u16) -> LedgerResult<()> {
+        if self.policy.strict_accounts {
+            self.account_repo.lock().await.get_account(client_id).await?;
+        }
+
+        Ok(())
+    }
+    // Closes an account without funds and open disputes, whether the funds
+    // are held on its own bookings or on the transfers it received.
+    async fn close(&mut self, tx: &Tx) -> LedgerResult<()> {
+        let disputed = self.bookings.lock().await.values()
+            .filter(|b| b.get_client_id() == tx.client_id || b.get_holder() == tx.client_id)
+            .any(|b| b.get_disputed() != Amount::ZERO);
+        if disputed {
+            return wrapped_booking_err("account has open disputes");
+        }
+
+        self.account_repo.lock().await.close_account(tx.client_id).await
+    }
+    // Applies a transaction that isn't a replay.
+    async fn apply_tx(&mut self, tx: Tx) -> LedgerResult<()> {
+        // Opening an account and accrual runs don't act on an existing
+        // account of the client.
+        match tx.tx_type {
+            TxType::Open => return self.account_repo.lock().await.open_account(tx.client_id).await,
+            TxType::Accrue => return self.accrue(&tx).await,
+            _ => {},
+        }
+        self.check_account(tx.client_id).await?;
+
+        // Administrative transactions don't create bookings and are meant for
+        // locked accounts.
+        match tx.tx_type {
+            TxType::Unlock => return self.unlock(&tx).await,
+            TxType::CreditLimit => return self.set_credit_limit(&tx).await,
+            TxType::SetStatus => return self.set_status(&tx).await,
+            _ => {},
+        }
 
-#[async_trait]
-impl BookingRepository for InMemoryBookingRepository {
-    async fn process_tx(&mut self, tx: Tx) -> LedgerResult<()> {
         // Check if account exists, if not create a new one.
         let account = self.account_repo.lock().await
             .get_or_create_account(tx.client_id).await?;
 
-        // Check if account is locked.
-        if account.is_locked() {
-            return wrapped_booking_err("account is locked");
+        // Check if the status of the account allows the transaction. An
+        // account locked by a chargeback only accepts its follow ups. A
+        // negative deposit or withdrawal moves the funds the other way and is
+        // checked as such.
+        if !account.get_status().allows(tx.get_direction()) {
+            return Err(booking_err(&format!("account is {}", account.get_status())));
+        }
+
+        // Closing doesn't refer to a booking of the client.
+        if tx.tx_type == TxType::Close {
+            return self.close(&tx).await;
         }
 
         // Check if booking exists and is unlocked. Return an error if locked.
-        let mut booking = self.get_or_create_booking(tx).await?;
+        let mut booking = self.get_or_create_booking(&tx).await?;
         if booking.is_locked() {
             return wrapped_booking_err("booking is locked");
         }
@@ -66,58 +731,369 @@ impl BookingRepository for InMemoryBookingRepository {
             return wrapped_booking_err("invalid transaction");
         }
 
+        // Follow up transactions have to be in the currency of the booking.
+        if tx.currency.as_ref().is_some_and(|c| c != booking.get_currency()) {
+            return wrapped_booking_err("currency does not match the booking");
+        }
+
         // Check previous booking state just in case we are dealing with 
         // two transactions with the same action.
-        match tx.tx_type {
-            // Deposit if booking is pristine.
+        // Every arm evaluates to the amount the transaction moved.
+        let applied = match tx.tx_type {
+            // Under a policy that allows negative amounts, a negative deposit
+            // takes funds out with the checks of a withdrawal, and a negative
+            // withdrawal brings them in like a deposit, without counting
+            // towards the withdrawal limits. Neither can be disputed.
+            TxType::Deposit if booking.get_amount().is_negative() => {
+                is_allowed_state(&booking, BookingState::Pristine)?;
+                self.withdraw(&tx, &account, &booking, (-booking.get_amount())?).await?;
+                booking.set_state_and_lock(BookingState::Normal);
+                booking.get_amount()
+            },
+            TxType::Withdrawal if booking.get_amount().is_negative() => {
+                is_allowed_state(&booking, BookingState::Pristine)?;
+                self.account_repo.lock().await
+                    .deposit(booking.get_client_id(), booking.get_currency(), (-booking.get_amount())?).await?;
+                booking.set_state_and_lock(BookingState::Normal);
+                booking.get_amount()
+            },
+
+            // Deposit if booking is pristine. Under a policy with pending
+            // deposits the funds can't be spent or disputed until settled.
+            TxType::Deposit if self.policy.pending_deposits => {
+                is_allowed_state(&booking, BookingState::Pristine)?;
+                self.account_repo.lock().await
+                    .deposit_pending(booking.get_client_id(), booking.get_currency(), booking.get_amount()).await?;
+                if let Some(delay) = self.policy.settlement_delay {
+                    self.add_deadline(delay, booking.get_tx_id()).await;
+                }
+                booking.set_state(BookingState::Pending);
+                booking.get_amount()
+            },
             TxType::Deposit => {
-                is_allowed_state(booking, BookingState::Pristine)?;
+                is_allowed_state(&booking, BookingState::Pristine)?;
+                self.account_repo.lock().await
+                    .deposit(booking.get_client_id(), booking.get_currency(), booking.get_amount()).await?;
+                booking.set_state(BookingState::Normal);
+                booking.get_amount()
+            },
+
+            TxType::Settle => {
+                is_allowed_state(&booking, BookingState::Pending)?;
                 self.account_repo.lock().await
-                    .deposit(booking.get_client_id(), booking.get_amount()).await?;
+                    .settle(booking.get_client_id(), booking.get_currency(), booking.get_amount()).await?;
                 booking.set_state(BookingState::Normal);
+                booking.get_amount()
+            },
+
+            // A rejected deposit never makes it to the available funds, so
+            // it's simply removed without a dispute.
+            TxType::RejectSettlement => {
+                is_allowed_state(&booking, BookingState::Pending)?;
+                self.account_repo.lock().await
+                    .reject_settlement(booking.get_client_id(), booking.get_currency(), booking.get_amount()).await?;
+                booking.set_state_and_lock(BookingState::Rejected);
+                booking.get_amount()
             },
 
             // Account repo decides if withdrawal is possible.
-            // *Assuming* that withdrawal can't be disputed.
+            // Withdrawal can only be disputed if the policy allows it.
             TxType::Withdrawal => {
-                is_allowed_state(booking, BookingState::Pristine)?;
-                self.account_repo.lock().await
-                    .withdraw(booking.get_client_id(), booking.get_amount()).await?;
-                booking.set_state_and_lock(BookingState::Normal);
+                is_allowed_state(&booking, BookingState::Pristine)?;
+                self.withdraw(&tx, &account, &booking, booking.get_amount()).await?;
+                if self.policy.disputable_withdrawals {
+                    booking.set_state(BookingState::Normal);
+                } else {
+                    booking.set_state_and_lock(BookingState::Normal);
+                }
+                booking.get_amount()
             },
 
-            // Dispute is handled by `hold` in account repo.
+            // Dispute is handled by `hold` in account repo. The funds are held
+            // wherever they ended up, so for a transfer it's the receiver.
+            // A dispute without an amount disputes the whole booking. The
+            // policy decides if it can take the holder past its credit limit.
             TxType::Dispute => {
-                is_allowed_state(booking, BookingState::Normal)?;
-                self.account_repo.lock().await
-                    .hold(booking.get_client_id(), booking.get_amount()).await?;
-                booking.set_state(BookingState::Disputed);
+                is_allowed_state(&booking, BookingState::Normal)?;
+                let amount = self.fit_amount(booking.get_currency(), tx.amount.unwrap_or(booking.get_amount()))?;
+                booking.open_dispute(tx.dispute.unwrap_or_default(), amount)?;
+                let mut account_repo = self.account_repo.lock().await;
+                if !self.policy.disputes_past_credit_limit && booking.get_tx_type() != TxType::Withdrawal {
+                    let holder = account_repo.get_or_create_account(booking.get_holder()).await?;
+                    if holder.get_headroom(booking.get_currency())? < amount {
+                        return wrapped_booking_err("dispute exceeds the credit limit");
+                    }
+                }
+                match booking.get_tx_type() {
+                    TxType::Withdrawal => account_repo
+                        .hold_claim(booking.get_client_id(), booking.get_currency(), amount).await?,
+                    _ => account_repo
+                        .hold(booking.get_holder(), booking.get_currency(), amount).await?,
+                }
+                amount
             },
 
             // Resolve is handled by `release` in account repo.
             TxType::Resolve => {
-                is_allowed_state(booking, BookingState::Disputed)?;
-                self.account_repo.lock().await
-                    .release(booking.get_client_id(), booking.get_amount()).await?;
-                booking.set_state_and_lock(BookingState::Resolved);
+                is_allowed_state(&booking, BookingState::Normal)?;
+                let amount = booking.close_dispute(tx.dispute.unwrap_or_default(), DisputeState::Resolved)?;
+                let mut account_repo = self.account_repo.lock().await;
+                match booking.get_tx_type() {
+                    TxType::Withdrawal => account_repo
+                        .drop_claim(booking.get_client_id(), booking.get_currency(), amount).await?,
+                    _ => account_repo
+                        .release(booking.get_holder(), booking.get_currency(), amount).await?,
+                }
+                amount
             },
 
             // Chargeback is handled by `withdraw` in account repo.
             // Account needs to be locked if this happens.
-            // *Assuming* that chargeback can make the account negative.
+            // The policy decides if chargeback can make the account negative.
+            // A charged back transfer returns the funds to the sender and a
+            // charged back withdrawal returns them to the client.
             TxType::Chargeback => {
-                is_allowed_state(booking, BookingState::Disputed)?;
+                is_allowed_state(&booking, BookingState::Normal)?;
+                let amount = booking.close_dispute(tx.dispute.unwrap_or_default(), DisputeState::Chargeback)?;
+                let mut account_repo = self.account_repo.lock().await;
+                if !self.policy.allow_negative_chargebacks && booking.get_tx_type() != TxType::Withdrawal {
+                    let holder = account_repo.get_or_create_account(booking.get_holder()).await?;
+                    if holder.get_total(booking.get_currency())? < amount {
+                        return wrapped_booking_err("chargeback would make the balance negative");
+                    }
+                }
+                match (booking.get_tx_type(), booking.get_counterparty()) {
+                    (TxType::Withdrawal, _) => account_repo
+                        .pay_claim(booking.get_client_id(), booking.get_currency(), amount).await?,
+                    (_, Some(to)) => account_repo
+                        .reverse_transfer(to, booking.get_client_id(), booking.get_currency(), amount).await?,
+                    (_, None) => account_repo
+                        .withdraw_and_lock(booking.get_client_id(), booking.get_currency(), amount).await?,
+                }
+                amount
+            },
+
+            // The merchant contested the chargeback, the funds don't move until
+            // the chargeback is reversed.
+            TxType::Representment => {
+                is_allowed_state(&booking, BookingState::Normal)?;
+                booking.represent_dispute(tx.dispute.unwrap_or_default())?
+            },
+
+            // A reversed chargeback moves the funds back to where they were
+            // before the dispute. The policy decides if the reversal unlocks
+            // the account the chargeback locked.
+            TxType::ChargebackReversal => {
+                is_allowed_state(&booking, BookingState::Normal)?;
+                let amount = booking.reverse_chargeback(tx.dispute.unwrap_or_default())?;
+                let mut account_repo = self.account_repo.lock().await;
+                match (booking.get_tx_type(), booking.get_counterparty()) {
+                    (TxType::Withdrawal, _) => account_repo
+                        .withdraw(booking.get_client_id(), booking.get_currency(), amount).await?,
+                    (_, Some(to)) => account_repo
+                        .reverse_transfer_chargeback(
+                            booking.get_client_id(),
+                            to,
+                            booking.get_currency(),
+                            amount,
+                            self.policy.unlock_on_chargeback_reversal,
+                        ).await?,
+                    (_, None) => account_repo
+                        .reverse_chargeback(
+                            booking.get_client_id(),
+                            booking.get_currency(),
+                            amount,
+                            self.policy.unlock_on_chargeback_reversal,
+                        ).await?,
+                }
+                amount
+            },
+
+            // Authorization reserves the funds until they are captured or
+            // voided, or until it expires.
+            TxType::Authorize => {
+                is_allowed_state(&booking, BookingState::Pristine)?;
+                if account.is_under_review(self.seq) {
+                    return wrapped_booking_err("account is under review");
+                }
+                self.account_repo.lock().await
+                    .authorize(booking.get_client_id(), booking.get_currency(), booking.get_amount()).await?;
+                if let Some(expiry) = self.policy.authorization_expiry {
+                    self.add_deadline(expiry, booking.get_tx_id()).await;
+                }
+                booking.set_state(BookingState::Authorized);
+                booking.get_amount()
+            },
+
+            // A capture without an amount captures the whole authorization,
+            // the rest of a smaller capture is released. Captured funds leave
+            // the ledger and can't be disputed.
+            TxType::Capture => {
+                is_allowed_state(&booking, BookingState::Authorized)?;
+                let amount = self.fit_amount(booking.get_currency(), tx.amount.unwrap_or(booking.get_amount()))?;
+                if amount <= Amount::ZERO || amount > booking.get_amount() {
+                    return wrapped_booking_err("invalid capture amount");
+                }
+                if account.is_under_review(self.seq) {
+                    return wrapped_booking_err("account is under review");
+                }
+                let capture = self.new_outflow(&tx, booking.get_currency(), amount);
+                self.check_limits(&account, &capture).await?;
+                self.account_repo.lock().await
+                    .capture(booking.get_client_id(), booking.get_currency(), booking.get_amount(), amount).await?;
+                self.record_outflow(booking.get_client_id(), capture).await;
+                booking.set_state_and_lock(BookingState::Normal);
+                amount
+            },
+
+            TxType::Void => {
+                is_allowed_state(&booking, BookingState::Authorized)?;
+                self.account_repo.lock().await
+                    .void(booking.get_client_id(), booking.get_currency(), booking.get_amount()).await?;
+                booking.set_state_and_lock(BookingState::Voided);
+                booking.get_amount()
+            },
+
+            // Conversion between two balances of the same client, the rate
+            // table decides the rate and the rounding. It can't be disputed.
+            TxType::Convert => {
+                is_allowed_state(&booking, BookingState::Pristine)?;
+                let to = tx.to_currency.as_ref().ok_or_else(|| booking_err("missing target currency"))?;
+                let mut fx_carry = self.fx_carry.lock().await;
+                let conversion = self.rates.convert(
+                    booking.get_currency(),
+                    booking.get_amount(),
+                    to,
+                    self.currencies.get_scale(to)?,
+                    self.seq,
+                    fx_carry.get(to).copied().unwrap_or_default(),
+                )?;
+                self.account_repo.lock().await
+                    .convert(booking.get_client_id(), &conversion).await?;
+                self.log(|undo| {
+                    undo.fx_carry.entry(to.clone()).or_insert_with(|| fx_carry.get(to).copied());
+                }).await;
+                fx_carry.insert(to.clone(), conversion.carry);
+                self.post_conversion(&booking, &conversion).await?;
+                booking.set_state_and_lock(BookingState::Normal);
+                booking.get_amount()
+            },
+
+            // Both legs of a transfer are applied by the account repo at once.
+            TxType::Transfer => {
+                is_allowed_state(&booking, BookingState::Pristine)?;
+                if account.is_under_review(self.seq) {
+                    return wrapped_booking_err("account is under review");
+                }
+                let to = booking.get_holder();
+                self.check_account(to).await?;
+                let transfer = self.new_outflow(&tx, booking.get_currency(), booking.get_amount());
+                self.check_limits(&account, &transfer).await?;
                 self.account_repo.lock().await
-                    .withdraw_and_lock(booking.get_client_id(), booking.get_amount()).await?;
-                booking.set_state_and_lock(BookingState::Chargeback);
+                    .transfer(booking.get_client_id(), to, booking.get_currency(), booking.get_amount()).await?;
+                self.record_outflow(booking.get_client_id(), transfer).await;
+                booking.set_state(BookingState::Normal);
+                booking.get_amount()
+            },
+
+            // Back-office corrections, they can't be disputed and only take
+            // the balance negative if the policy allows it.
+            TxType::AdjustmentCredit | TxType::AdjustmentDebit => {
+                is_allowed_state(&booking, BookingState::Pristine)?;
+                let adjustment = self.new_adjustment(&tx, &booking)?;
+                let mut account_repo = self.account_repo.lock().await;
+                let (client_id, currency, amount) = (booking.get_client_id(), booking.get_currency(), booking.get_amount());
+                match tx.tx_type {
+                    TxType::AdjustmentCredit => account_repo.deposit(client_id, currency, amount).await?,
+                    _ if self.policy.allow_negative_adjustments => account_repo.debit(client_id, currency, amount).await?,
+                    _ => account_repo.withdraw(client_id, currency, amount).await?,
+                }
+                self.adjustments.lock().await.push(adjustment);
+                booking.set_state_and_lock(BookingState::Normal);
+                booking.get_amount()
             },
+
+            TxType::Unlock | TxType::CreditLimit | TxType::SetStatus | TxType::Accrue | TxType::Open | TxType::Close => return wrapped_booking_err("not a booking transaction"),
         };
 
-        self.update_booking(tx.tx_id, booking).await
+        self.update_booking(tx.tx_id, booking.clone()).await?;
+        let metadata = tx.get_metadata();
+        self.record(tx.tx_type, &booking, applied, &metadata).await?;
+        self.charge_fee(&tx, &booking, applied).await
     }
 }
 
-fn is_allowed_state(current_booking: Booking, expected_state: BookingState) -> LedgerResult<()> {
+#[async_trait]
+impl BookingRepository for InMemoryBookingRepository {
+    // A transaction is applied in full or not at all, as a batch of one.
+    async fn process_tx(&mut self, tx: Tx) -> LedgerResult<()> {
+        self.begin_batch().await;
+        self.account_repo.lock().await.begin_batch().await?;
+        let result = self.process(tx).await;
+        self.end_batch(result).await
+    }
+    // Legs are applied one by one as usual. If any of them fails, both the
+    // bookings and the accounts go back to where they were before the batch.
+    async fn process_batch(&mut self, batch: &str, txs: Vec<Tx>) -> LedgerResult<()> {
+        if txs.is_empty() {
+            return wrapped_booking_err("empty batch");
+        }
+
+        self.begin_batch().await;
+        self.account_repo.lock().await.begin_batch().await?;
+        let mut result = Ok(());
+        for tx in txs {
+            let tx = Tx{batch: Some(batch.to_string()), ..tx};
+            result = self.process(tx).await;
+            if result.is_err() {
+                break;
+            }
+        }
+        self.end_batch(result).await
+    }
+    async fn dump_history(&self) -> LedgerResult<Vec<HistoryEntry>> {
+        Ok(self.history.lock().await.clone())
+    }
+    async fn dump_adjustments(&self) -> LedgerResult<Vec<Adjustment>> {
+        Ok(self.adjustments.lock().await.clone())
+    }
+    async fn dump_accruals(&self) -> LedgerResult<Vec<Accrual>> {
+        Ok(self.accruals.lock().await.clone())
+    }
+    async fn dump_risk_events(&self) -> LedgerResult<Vec<RiskEvent>> {
+        Ok(self.risk_events.lock().await.clone())
+    }
+    async fn dump_journal(&self) -> LedgerResult<Vec<JournalEntry>> {
+        Ok(self.journal.lock().await.get_entries().to_vec())
+    }
+    async fn trial_balance(&self) -> LedgerResult<Vec<TrialBalance>> {
+        self.journal.lock().await.trial_balance()
+    }
+    async fn dump_house_accounts(&self) -> LedgerResult<Vec<HouseSummary>> {
+        self.journal.lock().await.house_balances()
+    }
+    async fn dump_violations(&self) -> LedgerResult<Vec<LimitViolation>> {
+        Ok(self.violations.lock().await.iter()
+            .map(|((client, tier, limit), count)| LimitViolation{
+                client: *client,
+                tier: tier.clone(),
+                limit: *limit,
+                count: *count,
+            })
+            .collect())
+    }
+    async fn dump_disputes(&self) -> LedgerResult<Vec<DisputeSummary>> {
+        let mut disputes: Vec<DisputeSummary> = self.bookings.lock().await
+            .values()
+            .flat_map(|b| b.dispute_summaries())
+            .collect();
+        disputes.sort_by_key(|d| (d.tx, d.dispute));
+
+        Ok(disputes)
+    }
+}
+
+fn is_allowed_state(current_booking: &Booking, expected_state: BookingState) -> LedgerResult<()> {
     if current_booking.get_state() != expected_state {
         return wrapped_booking_err("transaction is not allowed");
     }
@@ -136,8 +1112,9 @@ fn wrapped_booking_err(msg: &str) -> Result<(), LedgerError> {
 #[cfg(test)]
 mod tests {
     use crate::dom::Amount;
-use crate::repo::account_repo::InMemoryAccountRepository;
-    use crate::dom::AccountSummary;
+    use crate::repo::account_repo::InMemoryAccountRepository;
+    use crate::app::Ledger;
+    use crate::dom::{AccountSpec, AccountSummary, BookingKind, BookingService, Currency, CurrencySpec, Exposure, ExposureReport, ReportService, FeeSpec, HouseAccount, HouseSummary, InterestSpec, LedgerErrorKind, LimitKind, LimitSpec, LimitTable, LimitViolation, RateSpec, ReasonCode, RiskAction, RiskEvent, RiskRule, RiskRuleKind, RiskRules, WindowUnit};
     use std::cmp::Ordering;
     use super::*;
 
@@ -149,182 +1126,1671 @@ use crate::repo::account_repo::InMemoryAccountRepository;
     struct CommonCases;
 
     impl CommonCases {
-        pub fn new() -> HashMap<&'static str, TestCase> {
-                return HashMap::from([
+        pub fn cases() -> HashMap<&'static str, TestCase> {
+                HashMap::from([
                     ("booking_gets_locked", TestCase {
                         txs: vec![
-                            (Tx{tx_id: 1, client_id: 1, tx_type: TxType::Deposit, amount: Some(Amount::from(10_0000))}, true),
-                            (Tx{tx_id: 1, client_id: 1, tx_type: TxType::Dispute, amount: None}, true),
-                            (Tx{tx_id: 1, client_id: 1, tx_type: TxType::Resolve, amount: None}, true),
+                            (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                            (Tx::new(1, 1, TxType::Dispute, None), true),
+                            (Tx::new(1, 1, TxType::Resolve, None), true),
                         ],
                         expected: vec![
-                            AccountSummary{client: 1, available: 10_0000.into(), total: 10_0000.into(), held: 0_0000.into(), locked: false}
+                            summary(1, 10_0000, 0_0000, 10_0000, false)
                         ]
                     }),
                     ("invalid_tx_1", TestCase {
                         txs: vec![
-                            (Tx{tx_id: 1, client_id: 1, tx_type: TxType::Deposit, amount: Some(Amount::from(10_0000))}, true),
-                            (Tx{tx_id: 1, client_id: 1, tx_type: TxType::Dispute, amount: None}, true),
-                            (Tx{tx_id: 0, client_id: 1, tx_type: TxType::Resolve, amount: None}, false),
+                            (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                            (Tx::new(1, 1, TxType::Dispute, None), true),
+                            (Tx::new(0, 1, TxType::Resolve, None), false),
                         ],
                         expected: vec![
-                            AccountSummary{client: 1, available: 0_0000.into(), total: 10_0000.into(), held: 10_0000.into(), locked: false}
+                            summary(1, 0_0000, 10_0000, 10_0000, false)
                         ]
                     }),
                     ("invalid_tx_2", TestCase {
                         txs: vec![
-                            (Tx{tx_id: 1, client_id: 1, tx_type: TxType::Deposit, amount: Some(Amount::from(10_0000))}, true),
-                            (Tx{tx_id: 1, client_id: 1, tx_type: TxType::Dispute, amount: None}, true),
-                            (Tx{tx_id: 1, client_id: 2, tx_type: TxType::Resolve, amount: None}, false),
+                            (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                            (Tx::new(1, 1, TxType::Dispute, None), true),
+                            // Rejected as a whole, without leaving an account
+                            // for client 2 behind.
+                            (Tx::new(1, 2, TxType::Resolve, None), false),
                         ],
                         expected: vec![
-                            AccountSummary{client: 2, available: 0_0000.into(), total: 0_0000.into(), held: 0_0000.into(), locked: false},
-                            AccountSummary{client: 1, available: 0_0000.into(), total: 10_0000.into(), held: 10_0000.into(), locked: false}
+                            summary(1, 0_0000, 10_0000, 10_0000, false)
                         ]
                     }),
                     ("deposit_booking", TestCase {
                         txs: vec![
-                            (Tx{tx_id: 1, client_id: 1, tx_type: TxType::Deposit, amount: Some(Amount::from(10_0000))}, true),
-                            (Tx{tx_id: 2, client_id: 2, tx_type: TxType::Deposit, amount: Some(Amount::from(11_0000))}, true),
-                            (Tx{tx_id: 3, client_id: 3, tx_type: TxType::Deposit, amount: Some(Amount::from(12_0000))}, true),
+                            (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                            (Tx::new(2, 2, TxType::Deposit, Some(Amount::from(11_0000))), true),
+                            (Tx::new(3, 3, TxType::Deposit, Some(Amount::from(12_0000))), true),
                         ],
                         expected: vec![
-                            AccountSummary{client: 1, available: 10_0000.into(), total: 10_0000.into(), held: 0_0000.into(), locked: false},
-                            AccountSummary{client: 2, available: 11_0000.into(), total: 11_0000.into(), held: 0_0000.into(), locked: false},
-                            AccountSummary{client: 3, available: 12_0000.into(), total: 12_0000.into(), held: 0_0000.into(), locked: false},
+                            summary(1, 10_0000, 0_0000, 10_0000, false),
+                            summary(2, 11_0000, 0_0000, 11_0000, false),
+                            summary(3, 12_0000, 0_0000, 12_0000, false),
                         ]
                     }),
                     ("tx_to_locked_account", TestCase {
                         txs: vec![
-                            (Tx{tx_id: 1, client_id: 1, tx_type: TxType::Deposit, amount: Some(Amount::from(10_0000))}, true),
-                            (Tx{tx_id: 1, client_id: 1, tx_type: TxType::Dispute, amount: None}, true),
-                            (Tx{tx_id: 1, client_id: 1, tx_type: TxType::Chargeback, amount: None}, true),
-                            (Tx{tx_id: 2, client_id: 1, tx_type: TxType::Deposit, amount: Some(Amount::from(1_0000))}, false),
+                            (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                            (Tx::new(1, 1, TxType::Dispute, None), true),
+                            (Tx::new(1, 1, TxType::Chargeback, None), true),
+                            (Tx::new(2, 1, TxType::Deposit, Some(Amount::from(1_0000))), false),
                         ],
                         expected: vec![
-                            AccountSummary{client: 1, available: 0_0000.into(), total: 0_0000.into(), held: 0_0000.into(), locked: true}
+                            summary(1, 0_0000, 0_0000, 0_0000, true)
                         ]
                     }),
                     ("multiple_txs_w_same_id", TestCase {
                         txs: vec![
-                            (Tx{tx_id: 1, client_id: 1, tx_type: TxType::Deposit, amount: Some(Amount::from(10_0000))}, true),
-                            (Tx{tx_id: 1, client_id: 1, tx_type: TxType::Dispute, amount: None}, true),
-                            (Tx{tx_id: 1, client_id: 1, tx_type: TxType::Dispute, amount: None}, false),
-                            (Tx{tx_id: 1, client_id: 1, tx_type: TxType::Resolve, amount: None}, true),
-                            (Tx{tx_id: 1, client_id: 1, tx_type: TxType::Resolve, amount: None}, false),
-                            (Tx{tx_id: 1, client_id: 1, tx_type: TxType::Chargeback, amount: None}, false),
-                            (Tx{tx_id: 1, client_id: 1, tx_type: TxType::Chargeback, amount: None}, false),
+                            (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                            (Tx::new(1, 1, TxType::Dispute, None), true),
+                            (Tx::new(1, 1, TxType::Dispute, None), true), // replay
+                            (Tx::new(1, 1, TxType::Resolve, None), true),
+                            (Tx::new(1, 1, TxType::Resolve, None), true), // replay
+                            (Tx::new(1, 1, TxType::Chargeback, None), false),
+                            (Tx::new(1, 1, TxType::Chargeback, None), false),
+                        ],
+                        expected: vec![
+                            summary(1, 10_0000, 0_0000, 10_0000, false)
+                        ]
+                    }),
+                    ("replayed_txs", TestCase {
+                        txs: vec![
+                            (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                            (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                            (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(20_0000))), false),
+                            (Tx::new(1, 1, TxType::Withdrawal, Some(Amount::from(10_0000))), false),
+                            (Tx::new(1, 2, TxType::Deposit, Some(Amount::from(10_0000))), false),
+                            (Tx::new(2, 1, TxType::Withdrawal, Some(Amount::from(3_0000))), true),
+                            (Tx::new(2, 1, TxType::Withdrawal, Some(Amount::from(3_0000))), true),
                         ],
                         expected: vec![
-                            AccountSummary{client: 1, available: 10_0000.into(), total: 10_0000.into(), held: 0_0000.into(), locked: false}
+                            summary(1, 7_0000, 0_0000, 7_0000, false),
                         ]
                     }),
                     ("withdraw_booking", TestCase {
                         txs: vec![
-                            (Tx{tx_id: 1, client_id: 1, tx_type: TxType::Deposit, amount: Some(Amount::from(10_0000))}, true),
-                            (Tx{tx_id: 2, client_id: 1, tx_type: TxType::Withdrawal, amount: Some(Amount::from(5_0000))}, true),
+                            (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                            (Tx::new(2, 1, TxType::Withdrawal, Some(Amount::from(5_0000))), true),
                         ],
                         expected: vec![
-                            AccountSummary{client: 1, available: 5_0000.into(), total: 5_0000.into(), held: 0_0000.into(), locked: false}
+                            summary(1, 5_0000, 0_0000, 5_0000, false)
                         ]
                     }),
                     ("dispute_booking", TestCase {
                         txs: vec![
-                            (Tx{tx_id: 1, client_id: 1, tx_type: TxType::Deposit, amount: Some(Amount::from(10_0000))}, true),
-                            (Tx{tx_id: 2, client_id: 1, tx_type: TxType::Deposit, amount: Some(Amount::from(5_0000))}, true),
-                            (Tx{tx_id: 2, client_id: 1, tx_type: TxType::Dispute, amount: None}, true),
-                            (Tx{tx_id: 3, client_id: 1, tx_type: TxType::Withdrawal, amount: Some(Amount::from(9_0000))}, true),
-                            (Tx{tx_id: 4, client_id: 1, tx_type: TxType::Withdrawal, amount: Some(Amount::from(9_0000))}, false),
+                            (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                            (Tx::new(2, 1, TxType::Deposit, Some(Amount::from(5_0000))), true),
+                            (Tx::new(2, 1, TxType::Dispute, None), true),
+                            (Tx::new(3, 1, TxType::Withdrawal, Some(Amount::from(9_0000))), true),
+                            (Tx::new(4, 1, TxType::Withdrawal, Some(Amount::from(9_0000))), false),
                         ],
                         expected: vec![
-                            AccountSummary{client: 1, available: 1_0000.into(), total: 6_0000.into(), held: 5_0000.into(), locked: false}
+                            summary(1, 1_0000, 5_0000, 6_0000, false)
                         ]
                     }),
                     ("resolve_booking", TestCase {
                         txs: vec![
-                            (Tx{tx_id: 1, client_id: 1, tx_type: TxType::Deposit, amount: Some(Amount::from(10_0000))}, true),
-                            (Tx{tx_id: 1, client_id: 1, tx_type: TxType::Dispute, amount: None}, true),
-                            (Tx{tx_id: 1, client_id: 1, tx_type: TxType::Resolve, amount: None}, true),
+                            (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                            (Tx::new(1, 1, TxType::Dispute, None), true),
+                            (Tx::new(1, 1, TxType::Resolve, None), true),
                         ],
                         expected: vec![
-                            AccountSummary{client: 1, available: 10_0000.into(), total: 10_0000.into(), held: 0_0000.into(), locked: false}
+                            summary(1, 10_0000, 0_0000, 10_0000, false)
                         ]
                     }),
                     ("chargeback_booking", TestCase {
                         txs: vec![
-                            (Tx{tx_id: 1, client_id: 1, tx_type: TxType::Deposit, amount: Some(Amount::from(10_0000))}, true),
-                            (Tx{tx_id: 2, client_id: 1, tx_type: TxType::Deposit, amount: Some(Amount::from(11_0000))}, true),
-                            (Tx{tx_id: 1, client_id: 1, tx_type: TxType::Dispute, amount: None}, true),
-                            (Tx{tx_id: 1, client_id: 1, tx_type: TxType::Chargeback, amount: None}, true),
+                            (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                            (Tx::new(2, 1, TxType::Deposit, Some(Amount::from(11_0000))), true),
+                            (Tx::new(1, 1, TxType::Dispute, None), true),
+                            (Tx::new(1, 1, TxType::Chargeback, None), true),
                         ],
                         expected: vec![
-                            AccountSummary{client: 1, available: 11_0000.into(), total: 11_0000.into(), held: 0_0000.into(), locked: true}
+                            summary(1, 11_0000, 0_0000, 11_0000, true)
                         ]
                     }),
-                    ("negative_chargeback_booking", TestCase {
+                    ("multi_currency_balances", TestCase {
                         txs: vec![
-                            (Tx{tx_id: 1, client_id: 1, tx_type: TxType::Deposit, amount: Some(Amount::from(10_0000))}, true),
-                            (Tx{tx_id: 2, client_id: 1, tx_type: TxType::Deposit, amount: Some(Amount::from(11_0000))}, true),
-                            (Tx{tx_id: 3, client_id: 1, tx_type: TxType::Withdrawal, amount: Some(Amount::from(20_0000))}, true),
-                            (Tx{tx_id: 2, client_id: 1, tx_type: TxType::Dispute, amount: None}, true), // available -10; held 11; total 1
-                            (Tx{tx_id: 4, client_id: 1, tx_type: TxType::Withdrawal, amount: Some(Amount::from(1_0000))}, false), // fails because of negative available balance
-                            (Tx{tx_id: 2, client_id: 1, tx_type: TxType::Chargeback, amount: None}, true), // available -10; held 0; total -10
+                            (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                            (Tx{currency: Some(usd()), ..Tx::new(2, 1, TxType::Deposit, Some(Amount::from(5_5000)))}, true),
+                            (Tx{currency: Some(usd()), ..Tx::new(3, 1, TxType::Withdrawal, Some(Amount::from(6_0000)))}, false),
+                            (Tx{currency: Some(usd()), ..Tx::new(4, 1, TxType::Withdrawal, Some(Amount::from(1_5000)))}, true),
+                            (Tx{currency: Some(eur()), ..Tx::new(5, 2, TxType::Deposit, Some(Amount::from(1_0000)))}, true),
                         ],
                         expected: vec![
-                            AccountSummary{client: 1, available: (-10_0000).into(), total: (-10_0000).into(), held: 0_0000.into(), locked: true}
+                            summary(1, 10_0000, 0_0000, 10_0000, false),
+                            AccountSummary{currency: usd(), ..summary(1, 4_0000, 0_0000, 4_0000, false)},
+                            AccountSummary{currency: eur(), ..summary(2, 1_0000, 0_0000, 1_0000, false)},
                         ]
                     }),
-                ])
-        }
-    }
-
-    fn new_booking_account_repo_pair() -> (InMemoryBookingRepository, Arc<Mutex<InMemoryAccountRepository>>) {
-        let account_repo = Arc::new(Mutex::new(InMemoryAccountRepository::new()));
-        (InMemoryBookingRepository::new(account_repo.clone()), account_repo)
-    }
-
-    #[tokio::test]
-    async fn booking_when_tx_without_existing_account() {
-        let tx = Tx{
-            tx_id: 1,
-            client_id: 1,
-            tx_type: TxType::Deposit,
-            amount: Some(Amount::from(10_0000)),
-        };
-        
-        let (mut booking_repo, account_repo) = new_booking_account_repo_pair();
-
-        let res = booking_repo.process_tx(tx).await;
-        assert!(res.is_ok());
-
-        let accounts = account_repo.lock().await.dump_accounts().await.unwrap();
-        assert_eq!(tx.client_id, accounts[0].client);
-        assert_eq!(tx.amount, Some(accounts[0].available.into()));
-        assert_eq!(tx.amount, Some(accounts[0].total.into()));
-        assert_eq!(Amount::from(0_0000), accounts[0].held);
-        assert_eq!(false, accounts[0].locked);
-    }
-
-    #[tokio::test]
-    async fn common_cases()  {
-        let cases = CommonCases::new();
-        for (title, mut case) in cases.into_iter() {
-            let (mut booking_repo, account_repo) = new_booking_account_repo_pair();
-
-            for (tx, should_succeed) in case.txs {
-                let res = booking_repo.process_tx(tx).await;
-                assert!(should_succeed == res.is_ok(), "{}: tx_id: {}", title, tx.tx_id);
-            }
-
-            assert_eq!(
-                case.expected.sort_by(summary_sort),
-                account_repo.lock().await.dump_accounts().await.unwrap().sort_by(summary_sort),
-                 "{}", title
-            );
-        }
-    }
-
-    fn summary_sort(a: &AccountSummary, b: &AccountSummary) -> Ordering {
-        a.client.partial_cmp(&b.client).unwrap()
+                    ("dispute_in_original_currency", TestCase {
+                        txs: vec![
+                            (Tx{currency: Some(usd()), ..Tx::new(1, 1, TxType::Deposit, Some(Amount::from(5_0000)))}, true),
+                            (Tx{currency: Some(eur()), ..Tx::new(1, 1, TxType::Dispute, None)}, false),
+                            (Tx::new(2, 1, TxType::Deposit, Some(Amount::from(1_0000))), true),
+                            (Tx{currency: Some(usd()), ..Tx::new(2, 1, TxType::Dispute, None)}, false),
+                            (Tx{currency: Some(usd()), ..Tx::new(1, 1, TxType::Dispute, None)}, true),
+                        ],
+                        expected: vec![
+                            summary(1, 1_0000, 0_0000, 1_0000, false),
+                            AccountSummary{currency: usd(), ..summary(1, 0_0000, 5_0000, 5_0000, false)},
+                        ]
+                    }),
+                    ("currency_scale", TestCase {
+                        txs: vec![
+                            (Tx{currency: Some(usd()), ..Tx::new(1, 1, TxType::Deposit, Some(Amount::from(1_0050)))}, false),
+                            (Tx{currency: Some(Currency::new("GBP")), ..Tx::new(2, 1, TxType::Deposit, Some(Amount::from(1_0000)))}, false),
+                            (Tx::new(3, 1, TxType::Deposit, Some(Amount::from(1_0050))), true),
+                        ],
+                        expected: vec![
+                            summary(1, 1_0050, 0_0000, 1_0050, false),
+                        ]
+                    }),
+                    ("convert_booking", TestCase {
+                        txs: vec![
+                            (Tx{currency: Some(eur()), ..Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0100)))}, true),
+                            (Tx{currency: Some(eur()), to_currency: Some(usd()), ..Tx::new(2, 1, TxType::Convert, Some(Amount::from(5_5500)))}, true),
+                            (Tx{currency: Some(eur()), to_currency: Some(usd()), ..Tx::new(3, 1, TxType::Convert, Some(Amount::from(10_0000)))}, false),
+                            (Tx{currency: Some(usd()), to_currency: Some(eur()), ..Tx::new(4, 1, TxType::Convert, Some(Amount::from(1_0000)))}, false),
+                            (Tx{currency: Some(eur()), ..Tx::new(5, 1, TxType::Convert, Some(Amount::from(1_0000)))}, false),
+                            (Tx::new(2, 1, TxType::Dispute, None), false),
+                        ],
+                        expected: vec![
+                            AccountSummary{currency: eur(), ..summary(1, 4_4600, 0_0000, 4_4600, false)},
+                            AccountSummary{currency: usd(), ..summary(1, 6_1000, 0_0000, 6_1000, false)},
+                        ]
+                    }),
+                    ("transfer_booking", TestCase {
+                        txs: vec![
+                            (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                            (Tx{to_client: Some(2), ..Tx::new(2, 1, TxType::Transfer, Some(Amount::from(4_0000)))}, true),
+                            (Tx{to_client: Some(2), ..Tx::new(3, 1, TxType::Transfer, Some(Amount::from(7_0000)))}, false),
+                            (Tx{to_client: Some(1), ..Tx::new(4, 1, TxType::Transfer, Some(Amount::from(1_0000)))}, false),
+                            (Tx::new(5, 1, TxType::Transfer, Some(Amount::from(1_0000))), false),
+                        ],
+                        expected: vec![
+                            summary(1, 6_0000, 0_0000, 6_0000, false),
+                            summary(2, 4_0000, 0_0000, 4_0000, false),
+                        ]
+                    }),
+                    ("failed_transfer_creates_no_receiver", TestCase {
+                        txs: vec![
+                            (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(3_0000))), true),
+                            (Tx{to_client: Some(4), ..Tx::new(2, 1, TxType::Transfer, Some(Amount::from(5_0000)))}, false),
+                        ],
+                        expected: vec![
+                            summary(1, 3_0000, 0_0000, 3_0000, false),
+                        ]
+                    }),
+                    ("transfer_to_locked_account", TestCase {
+                        txs: vec![
+                            (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                            (Tx::new(2, 2, TxType::Deposit, Some(Amount::from(1_0000))), true),
+                            (Tx::new(2, 2, TxType::Dispute, None), true),
+                            (Tx::new(2, 2, TxType::Chargeback, None), true),
+                            (Tx{to_client: Some(2), ..Tx::new(3, 1, TxType::Transfer, Some(Amount::from(4_0000)))}, false),
+                        ],
+                        expected: vec![
+                            summary(1, 10_0000, 0_0000, 10_0000, false),
+                            summary(2, 0_0000, 0_0000, 0_0000, true),
+                        ]
+                    }),
+                    ("transfer_dispute_resolve", TestCase {
+                        txs: vec![
+                            (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                            (Tx{to_client: Some(2), ..Tx::new(2, 1, TxType::Transfer, Some(Amount::from(4_0000)))}, true),
+                            (Tx::new(2, 2, TxType::Dispute, None), false),
+                            (Tx::new(2, 1, TxType::Dispute, None), true),
+                            (Tx::new(2, 1, TxType::Resolve, None), true),
+                        ],
+                        expected: vec![
+                            summary(1, 6_0000, 0_0000, 6_0000, false),
+                            summary(2, 4_0000, 0_0000, 4_0000, false),
+                        ]
+                    }),
+                    ("transfer_dispute_chargeback", TestCase {
+                        txs: vec![
+                            (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                            (Tx{to_client: Some(2), ..Tx::new(2, 1, TxType::Transfer, Some(Amount::from(4_0000)))}, true),
+                            (Tx::new(2, 1, TxType::Dispute, None), true), // client 2: available 0; held 4
+                            (Tx{to_client: Some(1), ..Tx::new(3, 2, TxType::Transfer, Some(Amount::from(1_0000)))}, false),
+                            (Tx::new(2, 1, TxType::Chargeback, None), true),
+                        ],
+                        expected: vec![
+                            summary(1, 10_0000, 0_0000, 10_0000, false),
+                            summary(2, 0_0000, 0_0000, 0_0000, true),
+                        ]
+                    }),
+                    ("unlock_with_review_period", TestCase {
+                        txs: vec![
+                            (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                            (Tx::new(2, 1, TxType::Deposit, Some(Amount::from(1_0000))), true),
+                            (Tx::new(2, 1, TxType::Dispute, None), true),
+                            (Tx::new(2, 1, TxType::Chargeback, None), true),
+                            (Tx{reason: Some(ReasonCode::ChargebackReviewed), ..Tx::new(3, 1, TxType::Unlock, None)}, false),
+                            (Tx{operator: Some("alice".into()), ..Tx::new(3, 1, TxType::Unlock, None)}, false),
+                            (Tx{operator: Some("alice".into()), reason: Some(ReasonCode::ChargebackReviewed), review_period: Some(2), ..Tx::new(3, 1, TxType::Unlock, None)}, true),
+                            (Tx{operator: Some("alice".into()), reason: Some(ReasonCode::ChargebackReviewed), ..Tx::new(4, 1, TxType::Unlock, None)}, false),
+                            (Tx::new(5, 1, TxType::Withdrawal, Some(Amount::from(1_0000))), false),
+                            (Tx::new(6, 1, TxType::Deposit, Some(Amount::from(1_0000))), true),
+                            (Tx::new(7, 1, TxType::Withdrawal, Some(Amount::from(1_0000))), true),
+                        ],
+                        expected: vec![
+                            summary(1, 10_0000, 0_0000, 10_0000, false),
+                        ]
+                    }),
+                    ("capture_under_review", TestCase {
+                        txs: vec![
+                            (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                            (Tx::new(2, 1, TxType::Deposit, Some(Amount::from(1_0000))), true),
+                            (Tx::new(3, 1, TxType::Authorize, Some(Amount::from(4_0000))), true),
+                            (Tx::new(2, 1, TxType::Dispute, None), true),
+                            (Tx::new(2, 1, TxType::Chargeback, None), true),
+                            (Tx{operator: Some("alice".into()), reason: Some(ReasonCode::ChargebackReviewed), review_period: Some(2), ..Tx::new(4, 1, TxType::Unlock, None)}, true),
+                            (Tx::new(3, 1, TxType::Capture, None), false),
+                            (Tx::new(5, 1, TxType::Deposit, Some(Amount::from(1_0000))), true),
+                            (Tx::new(3, 1, TxType::Capture, None), true),
+                        ],
+                        expected: vec![
+                            summary(1, 7_0000, 0_0000, 7_0000, false),
+                        ]
+                    }),
+                    ("adjustment_booking", TestCase {
+                        txs: vec![
+                            (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                            (Tx{reference: Some("CASE-1".into()), memo: Some("missed deposit".into()), ..Tx::new(2, 1, TxType::AdjustmentCredit, Some(Amount::from(2_0000)))}, true),
+                            (Tx{reference: Some("CASE-2".into()), ..Tx::new(3, 1, TxType::AdjustmentDebit, Some(Amount::from(1_0000)))}, false),
+                            (Tx{memo: Some("no reference".into()), ..Tx::new(4, 1, TxType::AdjustmentDebit, Some(Amount::from(1_0000)))}, false),
+                            (Tx{reference: Some("CASE-3".into()), memo: Some("too much".into()), ..Tx::new(5, 1, TxType::AdjustmentDebit, Some(Amount::from(13_0000)))}, false),
+                            (Tx{reference: Some("CASE-4".into()), memo: Some("fee refund".into()), ..Tx::new(6, 1, TxType::AdjustmentDebit, Some(Amount::from(5_0000)))}, true),
+                            (Tx::new(2, 1, TxType::Dispute, None), false),
+                            (Tx::new(6, 1, TxType::Dispute, None), false),
+                        ],
+                        expected: vec![
+                            summary(1, 7_0000, 0_0000, 7_0000, false),
+                        ]
+                    }),
+                    ("partial_disputes", TestCase {
+                        txs: vec![
+                            (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                            (Tx{dispute: Some(1), ..Tx::new(1, 1, TxType::Dispute, Some(Amount::from(3_0000)))}, true),
+                            (Tx{dispute: Some(2), ..Tx::new(1, 1, TxType::Dispute, Some(Amount::from(4_0000)))}, true),
+                            (Tx{dispute: Some(3), ..Tx::new(1, 1, TxType::Dispute, Some(Amount::from(3_0001)))}, false),
+                            (Tx{dispute: Some(2), ..Tx::new(1, 1, TxType::Dispute, Some(Amount::from(1_0000)))}, false),
+                            (Tx::new(1, 1, TxType::Dispute, None), false),
+                            (Tx{dispute: Some(1), ..Tx::new(1, 1, TxType::Resolve, None)}, true),
+                            (Tx{dispute: Some(1), ..Tx::new(1, 1, TxType::Chargeback, None)}, false),
+                            (Tx{dispute: Some(3), ..Tx::new(1, 1, TxType::Dispute, Some(Amount::from(6_0000)))}, true),
+                            (Tx{dispute: Some(4), ..Tx::new(1, 1, TxType::Dispute, Some(Amount::from(1)))}, false),
+                            (Tx{dispute: Some(3), ..Tx::new(1, 1, TxType::Resolve, None)}, true),
+                        ],
+                        expected: vec![
+                            summary(1, 6_0000, 4_0000, 10_0000, false),
+                        ]
+                    }),
+                    ("partial_dispute_chargeback", TestCase {
+                        txs: vec![
+                            (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                            (Tx{dispute: Some(1), ..Tx::new(1, 1, TxType::Dispute, Some(Amount::from(3_0000)))}, true),
+                            (Tx{dispute: Some(2), ..Tx::new(1, 1, TxType::Dispute, Some(Amount::from(2_0000)))}, true),
+                            (Tx{dispute: Some(2), ..Tx::new(1, 1, TxType::Chargeback, None)}, true),
+                            (Tx{dispute: Some(1), ..Tx::new(1, 1, TxType::Resolve, None)}, false),
+                        ],
+                        expected: vec![
+                            summary(1, 5_0000, 3_0000, 8_0000, true),
+                        ]
+                    }),
+                    ("chargeback_reversal", TestCase {
+                        txs: vec![
+                            (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                            (Tx::new(1, 1, TxType::Dispute, None), true),
+                            (Tx::new(1, 1, TxType::ChargebackReversal, None), false),
+                            (Tx::new(1, 1, TxType::Chargeback, None), true),
+                            (Tx::new(1, 1, TxType::Representment, None), true),
+                            (Tx::new(1, 1, TxType::ChargebackReversal, None), true),
+                            (Tx::new(1, 1, TxType::ChargebackReversal, None), true), // replay
+                            (Tx::new(2, 1, TxType::Deposit, Some(Amount::from(1_0000))), false),
+                        ],
+                        expected: vec![
+                            summary(1, 10_0000, 0_0000, 10_0000, true),
+                        ]
+                    }),
+                    ("transfer_chargeback_reversal", TestCase {
+                        txs: vec![
+                            (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                            (Tx{to_client: Some(2), ..Tx::new(2, 1, TxType::Transfer, Some(Amount::from(4_0000)))}, true),
+                            (Tx::new(2, 1, TxType::Dispute, None), true),
+                            (Tx::new(2, 1, TxType::Chargeback, None), true),
+                            (Tx::new(2, 1, TxType::ChargebackReversal, None), true),
+                        ],
+                        expected: vec![
+                            summary(1, 6_0000, 0_0000, 6_0000, false),
+                            summary(2, 4_0000, 0_0000, 4_0000, true),
+                        ]
+                    }),
+                    ("authorize_capture_void", TestCase {
+                        txs: vec![
+                            (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                            (Tx::new(2, 1, TxType::Authorize, Some(Amount::from(4_0000))), true),
+                            (Tx::new(3, 1, TxType::Authorize, Some(Amount::from(3_0000))), true),
+                            (Tx::new(4, 1, TxType::Authorize, Some(Amount::from(2_0000))), true),
+                            (Tx::new(5, 1, TxType::Authorize, Some(Amount::from(2_0000))), false),
+                            (Tx::new(2, 1, TxType::Capture, Some(Amount::from(4_0001))), false),
+                            (Tx::new(2, 1, TxType::Capture, Some(Amount::from(2_5000))), true),
+                            (Tx::new(2, 1, TxType::Void, None), false),
+                            (Tx::new(3, 1, TxType::Void, None), true),
+                            (Tx::new(3, 1, TxType::Capture, None), false),
+                            (Tx::new(4, 1, TxType::Dispute, None), false),
+                        ],
+                        expected: vec![
+                            AccountSummary{authorized: Amount::from(2_0000), ..summary(1, 5_5000, 0_0000, 7_5000, false)},
+                        ]
+                    }),
+                    ("dispute_unknown_booking", TestCase {
+                        txs: vec![
+                            (Tx::new(1, 1, TxType::Dispute, Some(Amount::from(1_0000))), false),
+                            (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(2_0000))), true),
+                        ],
+                        expected: vec![
+                            summary(1, 2_0000, 0_0000, 2_0000, false),
+                        ]
+                    }),
+                    ("overflowing_deposit", TestCase {
+                        txs: vec![
+                            (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(i64::MAX))), true),
+                            (Tx::new(2, 1, TxType::Deposit, Some(Amount::from(1))), false),
+                        ],
+                        expected: vec![
+                            summary(1, i64::MAX, 0_0000, i64::MAX, false)
+                        ]
+                    }),
+                    ("negative_chargeback_booking", TestCase {
+                        txs: vec![
+                            (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                            (Tx::new(2, 1, TxType::Deposit, Some(Amount::from(11_0000))), true),
+                            (Tx::new(3, 1, TxType::Withdrawal, Some(Amount::from(20_0000))), true),
+                            (Tx::new(2, 1, TxType::Dispute, None), true), // available -10; held 11; total 1
+                            (Tx::new(4, 1, TxType::Withdrawal, Some(Amount::from(1_0000))), false), // fails because of negative available balance
+                            (Tx::new(2, 1, TxType::Chargeback, None), true), // available -10; held 0; total -10
+                        ],
+                        expected: vec![
+                            summary(1, -10_0000, 0_0000, -10_0000, true)
+                        ]
+                    }),
+                ])
+        }
+    }
+
+    fn new_booking_account_repo_pair() -> (InMemoryBookingRepository, Arc<Mutex<InMemoryAccountRepository>>) {
+        let account_repo = Arc::new(Mutex::new(InMemoryAccountRepository::new()));
+        let currencies = Currencies::try_from(vec![
+            CurrencySpec{currency: usd(), scale: 2},
+            CurrencySpec{currency: eur(), scale: 2},
+        ]).unwrap();
+        let rates = RateTable::from_specs([
+            RateSpec{from: eur(), to: usd(), rate: "1.1".parse().unwrap(), effective_from: None},
+        ], RoundingMode::Down);
+        let booking_repo = InMemoryBookingRepository::new(account_repo.clone())
+            .with_currencies(currencies)
+            .with_rates(rates);
+        (booking_repo, account_repo)
+    }
+
+    #[tokio::test]
+    async fn booking_when_tx_without_existing_account() {
+        let tx = Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000)));
+        
+        let (mut booking_repo, account_repo) = new_booking_account_repo_pair();
+
+        let res = booking_repo.process_tx(tx.clone()).await;
+        assert!(res.is_ok());
+
+        let accounts = account_repo.lock().await.dump_accounts().await.unwrap();
+        assert_eq!(tx.client_id, accounts[0].client);
+        assert_eq!(tx.amount, Some(accounts[0].available));
+        assert_eq!(tx.amount, Some(accounts[0].total));
+        assert_eq!(Amount::from(0_0000), accounts[0].held);
+        assert!(!accounts[0].locked);
+    }
+
+    #[tokio::test]
+    async fn convert_books_residue_to_house_account() {
+        let (mut booking_repo, _) = new_booking_account_repo_pair();
+        let txs = vec![
+            Tx{currency: Some(eur()), ..Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000)))},
+            // 5.55 EUR * 1.1 = 6.105 USD, rounded down to 6.10 USD.
+            Tx{currency: Some(eur()), to_currency: Some(usd()), ..Tx::new(2, 1, TxType::Convert, Some(Amount::from(5_5500)))},
+            Tx{currency: Some(eur()), to_currency: Some(usd()), ..Tx::new(3, 1, TxType::Convert, Some(Amount::from(300)))},
+        ];
+        for tx in txs {
+            booking_repo.process_tx(tx).await.unwrap();
+        }
+
+        let house = booking_repo.dump_house_accounts().await.unwrap();
+        assert_eq!(vec![
+            HouseSummary{account: HouseAccount::FxResidue, currency: usd(), balance: Amount::from(80)},
+        ], house);
+    }
+
+    #[tokio::test]
+    async fn fees_are_booked_and_recorded() {
+        let (booking_repo, account_repo) = new_booking_account_repo_pair();
+        let fees = FeeSchedule::from_specs([
+            FeeSpec{tx_type: TxType::Withdrawal, currency: None, flat: Some(Amount::from(5000)), rate: None},
+            FeeSpec{tx_type: TxType::Chargeback, currency: None, flat: Some(Amount::from(15_0000)), rate: None},
+        ], RoundingMode::Down);
+        let mut booking_repo = booking_repo.with_fees(fees);
+        let txs = vec![
+            (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+            (Tx::new(2, 1, TxType::Withdrawal, Some(Amount::from(4_0000))), true),
+            (Tx::new(3, 1, TxType::Withdrawal, Some(Amount::from(6_0000))), false),
+            (Tx::new(1, 1, TxType::Dispute, None), true),
+            (Tx::new(1, 1, TxType::Chargeback, None), true),
+        ];
+        for (tx, should_succeed) in txs {
+            let tx_id = tx.tx_id;
+            assert_eq!(should_succeed, booking_repo.process_tx(tx).await.is_ok(), "tx_id: {}", tx_id);
+        }
+
+        let accounts = account_repo.lock().await.dump_accounts().await.unwrap();
+        assert_eq!(vec![
+            AccountSummary{fees: 15_5000.into(), ..summary(1, -19_5000, 0_0000, -19_5000, true)},
+        ], accounts);
+
+        let house = booking_repo.dump_house_accounts().await.unwrap();
+        assert_eq!(vec![
+            HouseSummary{account: HouseAccount::FeeIncome, currency: Currency::default(), balance: Amount::from(15_5000)},
+        ], house);
+
+        let fees: Vec<(u64, u32, TxType, Amount)> = booking_repo.dump_history().await.unwrap().into_iter()
+            .filter(|e| e.kind == BookingKind::Fee)
+            .map(|e| (e.seq, e.tx, e.tx_type, e.amount))
+            .collect();
+        assert_eq!(vec![
+            (2, 2, TxType::Withdrawal, Amount::from(5000)),
+            (5, 1, TxType::Chargeback, Amount::from(15_0000)),
+        ], fees);
+    }
+
+    #[tokio::test]
+    async fn transfer_dispute_fees_are_paid_by_the_receiver() {
+        let (booking_repo, account_repo) = new_booking_account_repo_pair();
+        let fees = FeeSchedule::from_specs([
+            FeeSpec{tx_type: TxType::Dispute, currency: None, flat: Some(Amount::from(5000)), rate: None},
+            FeeSpec{tx_type: TxType::Chargeback, currency: None, flat: Some(Amount::from(1_0000)), rate: None},
+        ], RoundingMode::Down);
+        let mut booking_repo = booking_repo.with_fees(fees);
+        let txs = vec![
+            Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))),
+            Tx{to_client: Some(2), ..Tx::new(2, 1, TxType::Transfer, Some(Amount::from(4_0000)))},
+            Tx::new(2, 1, TxType::Dispute, None),
+            Tx::new(2, 1, TxType::Chargeback, None),
+        ];
+        for tx in txs {
+            booking_repo.process_tx(tx).await.unwrap();
+        }
+
+        let mut accounts = account_repo.lock().await.dump_accounts().await.unwrap();
+        accounts.sort_by(summary_sort);
+        assert_eq!(vec![
+            summary(1, 10_0000, 0_0000, 10_0000, false),
+            AccountSummary{fees: 1_5000.into(), ..summary(2, -1_5000, 0_0000, -1_5000, true)},
+        ], accounts);
+        assert_books_mirror_accounts(&booking_repo, &accounts).await;
+
+        let fee_bookings = booking_repo.fee_bookings.lock().await;
+        let fee = fee_bookings.get(&TxKey{tx_id: 2, tx_type: Some(TxType::Dispute), dispute: None}).unwrap();
+        assert_eq!((2, BookingKind::Fee, Amount::from(5000)), (fee.get_client_id(), fee.get_kind(), fee.get_amount()));
+        assert!(fee.is_locked());
+        assert_eq!(2, fee_bookings.len());
+    }
+
+    #[tokio::test]
+    async fn interest_is_accrued_once_per_period() {
+        let (booking_repo, account_repo) = new_booking_account_repo_pair();
+        let interest = InterestTable::from_specs([
+            InterestSpec{currency: None, from: Amount::ZERO, rate: "0.01".parse().unwrap()},
+            InterestSpec{currency: None, from: Amount::from(100_0000), rate: "0.02".parse().unwrap()},
+        ], RoundingMode::Down);
+        let mut booking_repo = booking_repo.with_interest(interest);
+        let accrue = |tx_id, period: &str| Tx{period: Some(period.into()), ..Tx::new(tx_id, 1, TxType::Accrue, None)};
+        let txs = vec![
+            (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(150_0000))), true),
+            (Tx{currency: Some(usd()), ..Tx::new(2, 1, TxType::Deposit, Some(Amount::from(10_0000)))}, true),
+            (Tx::new(3, 2, TxType::Deposit, Some(Amount::from(5_0000))), true),
+            (Tx::new(4, 3, TxType::Deposit, Some(Amount::from(5_0000))), true),
+            (Tx{operator: Some("bob".into()), reason: Some(ReasonCode::CustomerRequest), status: Some(AccountStatus::DepositsFrozen), ..Tx::new(5, 3, TxType::SetStatus, None)}, true),
+            // Accrues for every account but the frozen one.
+            (accrue(6, "2024-01"), true),
+            (accrue(7, "2024-01"), false),
+            (Tx{period: Some("2024-01".into()), ..Tx::new(8, 9, TxType::Accrue, None)}, false),
+            (accrue(9, ""), false),
+            (Tx::new(10, 1, TxType::Accrue, None), false),
+            (accrue(11, "2024-13"), false),
+            (accrue(12, "24-02"), false),
+            (Tx{currency: Some(usd()), ..accrue(13, "2024-02")}, true),
+        ];
+        for (tx, should_succeed) in txs {
+            let tx_id = tx.tx_id;
+            assert_eq!(should_succeed, booking_repo.process_tx(tx).await.is_ok(), "tx_id: {}", tx_id);
+        }
+
+        let mut accounts = account_repo.lock().await.dump_accounts().await.unwrap();
+        accounts.sort_by(summary_sort);
+        assert_eq!(vec![
+            // 1% of 100 + 2% of 50
+            summary(1, 152_0000, 0_0000, 152_0000, false),
+            // 1% of 10, then 1% of 10.10 rounded down to cents
+            AccountSummary{currency: usd(), ..summary(1, 10_2000, 0_0000, 10_2000, false)},
+            summary(2, 5_0500, 0_0000, 5_0500, false),
+            AccountSummary{status: AccountStatus::DepositsFrozen, ..summary(3, 5_0000, 0_0000, 5_0000, false)},
+        ], accounts);
+
+        let house = booking_repo.dump_house_accounts().await.unwrap();
+        assert_eq!(vec![
+            HouseSummary{account: HouseAccount::InterestExpense, currency: Currency::default(), balance: Amount::from(2_0500)},
+            HouseSummary{account: HouseAccount::InterestExpense, currency: usd(), balance: Amount::from(2000)},
+        ], house);
+
+        let accruals: Vec<(u32, u16, Amount)> = booking_repo.dump_accruals().await.unwrap().into_iter()
+            .map(|a| (a.tx, a.client, a.interest))
+            .collect();
+        assert_eq!(vec![
+            (6, 1, Amount::from(2_0000)),
+            (6, 1, Amount::from(1000)),
+            (6, 2, Amount::from(500)),
+            (13, 1, Amount::from(1000)),
+        ], accruals);
+
+        let interest: Vec<(u32, u16, Amount)> = booking_repo.interest_bookings.lock().await.iter()
+            .map(|b| (b.get_tx_id(), b.get_client_id(), b.get_amount()))
+            .collect();
+        assert_eq!(vec![
+            (6, 1, Amount::from(2_0000)),
+            (6, 1, Amount::from(1000)),
+            (6, 2, Amount::from(500)),
+            (13, 1, Amount::from(1000)),
+        ], interest);
+    }
+
+    #[tokio::test]
+    async fn failed_tx_is_undone_in_full() {
+        let (booking_repo, account_repo) = new_booking_account_repo_pair();
+        let interest = InterestTable::from_specs([
+            InterestSpec{currency: None, from: Amount::ZERO, rate: "0.01".parse().unwrap()},
+        ], RoundingMode::Down);
+        let mut booking_repo = booking_repo.with_interest(interest);
+        let txs = vec![
+            (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(100_0000))), true),
+            (Tx::new(2, 2, TxType::Deposit, Some(Amount::from(i64::MAX - 200_0000))), true),
+            // Client 1 is paid before the interest of client 2 overflows.
+            (Tx{period: Some("2024-01".into()), ..Tx::new(3, 1, TxType::Accrue, None)}, false),
+        ];
+        for (tx, should_succeed) in txs {
+            let tx_id = tx.tx_id;
+            assert_eq!(should_succeed, booking_repo.process_tx(tx).await.is_ok(), "tx_id: {}", tx_id);
+        }
+
+        let mut accounts = account_repo.lock().await.dump_accounts().await.unwrap();
+        accounts.sort_by(summary_sort);
+        assert_eq!(vec![
+            summary(1, 100_0000, 0_0000, 100_0000, false),
+            summary(2, i64::MAX - 200_0000, 0_0000, i64::MAX - 200_0000, false),
+        ], accounts);
+        assert!(booking_repo.dump_accruals().await.unwrap().is_empty());
+        assert!(booking_repo.interest_bookings.lock().await.is_empty());
+        assert!(booking_repo.dump_house_accounts().await.unwrap().is_empty());
+        assert_eq!(4, booking_repo.dump_journal().await.unwrap().len());
+        assert_books_mirror_accounts(&booking_repo, &accounts).await;
+    }
+
+    #[tokio::test]
+    async fn metadata_is_recorded() {
+        let (booking_repo, _) = new_booking_account_repo_pair();
+        let fees = FeeSchedule::from_specs([
+            FeeSpec{tx_type: TxType::Deposit, currency: None, flat: Some(Amount::from(1000)), rate: None},
+        ], RoundingMode::Down);
+        let mut booking_repo = booking_repo.with_fees(fees);
+        let txs = vec![
+            Tx{timestamp: Some(100), reference: Some("PSP-1".into()), memo: Some("top up".into()), ..Tx::new(1, 1, TxType::Deposit, Some(Amount::from(1_0000)))},
+            Tx{timestamp: Some(200), reference: Some("CASE-7".into()), ..Tx::new(1, 1, TxType::Dispute, None)},
+        ];
+        for tx in txs {
+            booking_repo.process_tx(tx).await.unwrap();
+        }
+
+        let booking = booking_repo.bookings.lock().await.get(&1).cloned().unwrap();
+        assert_eq!(&TxMetadata{timestamp: Some(100), reference: Some("PSP-1".into()), memo: Some("top up".into()), batch: None}, booking.get_metadata());
+
+        let history: Vec<(TxType, BookingKind, Option<u64>, Option<String>)> = booking_repo.dump_history().await.unwrap().into_iter()
+            .map(|e| (e.tx_type, e.kind, e.timestamp, e.reference))
+            .collect();
+        assert_eq!(vec![
+            (TxType::Deposit, BookingKind::Transaction, Some(100), Some("PSP-1".into())),
+            (TxType::Deposit, BookingKind::Fee, Some(100), Some("PSP-1".into())),
+            (TxType::Dispute, BookingKind::Transaction, Some(200), Some("CASE-7".into())),
+        ], history);
+    }
+
+    #[tokio::test]
+    async fn conflicting_tx_is_not_a_replay() {
+        let (mut booking_repo, _) = new_booking_account_repo_pair();
+        booking_repo.process_tx(Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000)))).await.unwrap();
+        booking_repo.process_tx(Tx{dispute: Some(1), ..Tx::new(1, 1, TxType::Dispute, Some(Amount::from(2_0000)))}).await.unwrap();
+
+        let cases = vec![
+            Tx::new(1, 1, TxType::Deposit, Some(Amount::from(1_0000))),
+            Tx::new(1, 1, TxType::Withdrawal, Some(Amount::from(10_0000))),
+            Tx{dispute: Some(1), ..Tx::new(1, 1, TxType::Dispute, Some(Amount::from(3_0000)))},
+        ];
+        for tx in cases {
+            let err = booking_repo.process_tx(tx.clone()).await.unwrap_err();
+            assert!(matches!(err.kind(), LedgerErrorKind::Conflict(_)), "{:?}: {}", tx, err);
+        }
+
+        // Not a replay, the dispute with a new id is refused as before.
+        let err = booking_repo.process_tx(Tx{dispute: Some(2), ..Tx::new(1, 1, TxType::Dispute, Some(Amount::from(9_0000)))}).await.unwrap_err();
+        assert!(matches!(err.kind(), LedgerErrorKind::RepositoryError(_)), "{}", err);
+
+        // Administrative transactions conflict on any of their values.
+        let unlock = Tx{operator: Some("alice".into()), reason: Some(ReasonCode::ChargebackReviewed), review_period: Some(2), ..Tx::new(2, 1, TxType::Unlock, None)};
+        let status = Tx{operator: Some("alice".into()), reason: Some(ReasonCode::CustomerRequest), status: Some(AccountStatus::DepositsFrozen), ..Tx::new(3, 1, TxType::SetStatus, None)};
+        booking_repo.process_tx(Tx{dispute: Some(1), ..Tx::new(1, 1, TxType::Chargeback, None)}).await.unwrap();
+        booking_repo.process_tx(unlock.clone()).await.unwrap();
+        booking_repo.process_tx(status.clone()).await.unwrap();
+        booking_repo.process_tx(unlock.clone()).await.unwrap();
+        booking_repo.process_tx(status.clone()).await.unwrap();
+
+        let cases = vec![
+            Tx{operator: Some("bob".into()), ..unlock.clone()},
+            Tx{reason: Some(ReasonCode::FraudCleared), ..unlock.clone()},
+            Tx{review_period: Some(5), ..unlock.clone()},
+            Tx{review_period: None, ..unlock},
+            Tx{status: Some(AccountStatus::FullyFrozen), ..status},
+        ];
+        for tx in cases {
+            let err = booking_repo.process_tx(tx.clone()).await.unwrap_err();
+            assert!(matches!(err.kind(), LedgerErrorKind::Conflict(_)), "{:?}: {}", tx, err);
+        }
+    }
+
+    #[tokio::test]
+    async fn rejected_tx_leaves_no_booking() {
+        let (mut booking_repo, account_repo) = new_booking_account_repo_pair();
+        let txs = vec![
+            (Tx::new(5, 1, TxType::Withdrawal, Some(Amount::from(100_0000))), false),
+            (Tx::new(5, 1, TxType::Deposit, Some(Amount::from(1_0000))), true),
+            (Tx::new(5, 1, TxType::Dispute, None), true),
+            (Tx::new(5, 1, TxType::Deposit, Some(Amount::from(2_0000))), false),
+        ];
+        for (tx, should_succeed) in txs {
+            let tx_type = tx.tx_type;
+            assert_eq!(should_succeed, booking_repo.process_tx(tx).await.is_ok(), "{:?}", tx_type);
+        }
+
+        let accounts = account_repo.lock().await.dump_accounts().await.unwrap();
+        assert_eq!(vec![summary(1, 0_0000, 1_0000, 1_0000, false)], accounts);
+    }
+
+    #[tokio::test]
+    async fn replay_does_not_move_the_clock() {
+        let (booking_repo, _) = new_booking_account_repo_pair();
+        let mut booking_repo = booking_repo.with_policy(LedgerPolicy{authorization_expiry: Some(1), ..LedgerPolicy::default()});
+        let deposit = Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000)));
+        booking_repo.process_tx(deposit.clone()).await.unwrap();
+        booking_repo.process_tx(Tx::new(2, 1, TxType::Authorize, Some(Amount::from(4_0000)))).await.unwrap();
+        booking_repo.process_tx(deposit.clone()).await.unwrap();
+        assert!(booking_repo.process_tx(Tx::new(1, 1, TxType::Deposit, Some(Amount::from(1_0000)))).await.is_err());
+        booking_repo.process_tx(Tx::new(2, 1, TxType::Capture, None)).await.unwrap();
+
+        let seqs: Vec<(u64, TxType)> = booking_repo.dump_history().await.unwrap().into_iter()
+            .map(|e| (e.seq, e.tx_type))
+            .collect();
+        assert_eq!(vec![(1, TxType::Deposit), (2, TxType::Authorize), (3, TxType::Capture)], seqs);
+    }
+
+    #[tokio::test]
+    async fn batch_is_all_or_nothing() {
+        let (booking_repo, account_repo) = new_booking_account_repo_pair();
+        let fees = FeeSchedule::from_specs([
+            FeeSpec{tx_type: TxType::Deposit, currency: None, flat: Some(Amount::from(1_0000)), rate: None},
+        ], RoundingMode::Down);
+        let mut booking_repo = booking_repo.with_fees(fees);
+        booking_repo.process_tx(Tx::new(1, 1, TxType::Deposit, Some(Amount::from(5_0000)))).await.unwrap();
+
+        // The transfer to the reserve account needs more than was deposited.
+        let failing = vec![
+            Tx::new(2, 1, TxType::Deposit, Some(Amount::from(10_0000))),
+            Tx{to_client: Some(9), ..Tx::new(3, 1, TxType::Transfer, Some(Amount::from(20_0000)))},
+        ];
+        assert!(booking_repo.process_batch("b1", failing).await.is_err());
+        assert!(booking_repo.process_batch("b2", vec![]).await.is_err());
+
+        let accounts = account_repo.lock().await.dump_accounts().await.unwrap();
+        assert_eq!(vec![AccountSummary{fees: 1_0000.into(), ..summary(1, 4_0000, 0_0000, 4_0000, false)}], accounts);
+        let house = booking_repo.dump_house_accounts().await.unwrap();
+        assert_eq!(vec![
+            HouseSummary{account: HouseAccount::FeeIncome, currency: Currency::default(), balance: Amount::from(1_0000)},
+        ], house);
+        assert_eq!(2, booking_repo.dump_history().await.unwrap().len());
+
+        // The same legs can be submitted again once they fit.
+        let passing = vec![
+            Tx::new(2, 1, TxType::Deposit, Some(Amount::from(10_0000))),
+            Tx{to_client: Some(9), ..Tx::new(3, 1, TxType::Transfer, Some(Amount::from(12_0000)))},
+        ];
+        booking_repo.process_batch("b3", passing).await.unwrap();
+
+        let mut accounts = account_repo.lock().await.dump_accounts().await.unwrap();
+        accounts.sort_by(summary_sort);
+        assert_eq!(vec![
+            AccountSummary{fees: 2_0000.into(), ..summary(1, 1_0000, 0_0000, 1_0000, false)},
+            summary(9, 12_0000, 0_0000, 12_0000, false),
+        ], accounts);
+        // The legs of the failed batch still took up positions 2 and 3.
+        let batches: Vec<(u64, Option<String>)> = booking_repo.dump_history().await.unwrap().into_iter()
+            .map(|e| (e.seq, e.batch))
+            .collect();
+        assert_eq!(vec![
+            (1, None),
+            (1, None),
+            (4, Some("b3".into())),
+            (4, Some("b3".into())),
+            (5, Some("b3".into())),
+        ], batches);
+    }
+
+    #[tokio::test]
+    async fn unlock_is_recorded() {
+        let (mut booking_repo, account_repo) = new_booking_account_repo_pair();
+        let txs = vec![
+            Tx::new(1, 1, TxType::Deposit, Some(Amount::from(1_0000))),
+            Tx::new(1, 1, TxType::Dispute, None),
+            Tx::new(1, 1, TxType::Chargeback, None),
+            Tx{operator: Some("bob".into()), reason: Some(ReasonCode::OperationalError), review_period: Some(5), ..Tx::new(2, 1, TxType::Unlock, None)},
+        ];
+        for tx in txs {
+            booking_repo.process_tx(tx).await.unwrap();
+        }
+
+        let unlocks = account_repo.lock().await.dump_unlocks().await.unwrap();
+        assert_eq!(vec![
+            Unlock{client: 1, operator: "bob".into(), reason: ReasonCode::OperationalError, seq: 4, review_until: Some(9), timestamp: None, reference: None, memo: None},
+        ], unlocks);
+    }
+
+    #[tokio::test]
+    async fn negative_adjustment_by_policy() {
+        let (booking_repo, account_repo) = new_booking_account_repo_pair();
+        let mut booking_repo = booking_repo.with_policy(LedgerPolicy{allow_negative_adjustments: true, ..LedgerPolicy::default()});
+        let txs = vec![
+            Tx::new(1, 1, TxType::Deposit, Some(Amount::from(1_0000))),
+            Tx{reference: Some("CASE-1".into()), memo: Some("chargeback loss".into()), ..Tx::new(2, 1, TxType::AdjustmentDebit, Some(Amount::from(3_0000)))},
+        ];
+        for tx in txs {
+            booking_repo.process_tx(tx).await.unwrap();
+        }
+
+        let accounts = account_repo.lock().await.dump_accounts().await.unwrap();
+        assert_eq!(vec![summary(1, -2_0000, 0_0000, -2_0000, false)], accounts);
+
+        let adjustments = booking_repo.dump_adjustments().await.unwrap();
+        assert_eq!(vec![
+            Adjustment{seq: 2, tx: 2, client: 1, tx_type: TxType::AdjustmentDebit, amount: Amount::from(3_0000), currency: Currency::default(), timestamp: None, reference: "CASE-1".into(), memo: "chargeback loss".into()},
+        ], adjustments);
+    }
+
+    #[tokio::test]
+    async fn chargeback_reversal_unlocks_by_policy() {
+        let (booking_repo, account_repo) = new_booking_account_repo_pair();
+        let mut booking_repo = booking_repo.with_policy(LedgerPolicy{unlock_on_chargeback_reversal: true, ..LedgerPolicy::default()});
+        let txs = vec![
+            Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))),
+            Tx{dispute: Some(1), ..Tx::new(1, 1, TxType::Dispute, Some(Amount::from(4_0000)))},
+            Tx{dispute: Some(2), ..Tx::new(1, 1, TxType::Dispute, Some(Amount::from(6_0000)))},
+            Tx{dispute: Some(1), ..Tx::new(1, 1, TxType::Chargeback, None)},
+            Tx{dispute: Some(1), ..Tx::new(1, 1, TxType::ChargebackReversal, None)},
+            Tx{dispute: Some(2), ..Tx::new(1, 1, TxType::Resolve, None)},
+        ];
+        for tx in txs {
+            booking_repo.process_tx(tx).await.unwrap();
+        }
+
+        let accounts = account_repo.lock().await.dump_accounts().await.unwrap();
+        assert_eq!(vec![summary(1, 10_0000, 0_0000, 10_0000, false)], accounts);
+
+        let disputes = booking_repo.dump_disputes().await.unwrap();
+        assert_eq!(vec![
+            DisputeSummary{tx: 1, client: 1, dispute: 1, amount: Amount::from(4_0000), currency: Currency::default(), state: DisputeState::Reversed, holder: 1, tx_type: TxType::Deposit},
+            DisputeSummary{tx: 1, client: 1, dispute: 2, amount: Amount::from(6_0000), currency: Currency::default(), state: DisputeState::Resolved, holder: 1, tx_type: TxType::Deposit},
+        ], disputes);
+    }
+
+    #[tokio::test]
+    async fn authorizations_expire() {
+        let (booking_repo, account_repo) = new_booking_account_repo_pair();
+        let mut booking_repo = booking_repo.with_policy(LedgerPolicy{authorization_expiry: Some(2), ..LedgerPolicy::default()});
+        let txs = vec![
+            (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+            (Tx::new(2, 1, TxType::Authorize, Some(Amount::from(4_0000))), true),
+            (Tx::new(3, 1, TxType::Authorize, Some(Amount::from(3_0000))), true),
+            (Tx::new(4, 1, TxType::Deposit, Some(Amount::from(1_0000))), true),
+            (Tx::new(3, 1, TxType::Capture, None), true),
+            (Tx::new(2, 1, TxType::Capture, None), false),
+        ];
+        for (tx, should_succeed) in txs {
+            let tx_id = tx.tx_id;
+            assert_eq!(should_succeed, booking_repo.process_tx(tx).await.is_ok(), "tx_id: {}", tx_id);
+        }
+
+        let accounts = account_repo.lock().await.dump_accounts().await.unwrap();
+        assert_eq!(vec![summary(1, 8_0000, 0_0000, 8_0000, false)], accounts);
+
+        let voids: Vec<(u64, u32)> = booking_repo.dump_history().await.unwrap().iter()
+            .filter(|e| e.tx_type == TxType::Void)
+            .map(|e| (e.seq, e.tx))
+            .collect();
+        assert_eq!(vec![(5, 2)], voids);
+    }
+
+    #[tokio::test]
+    async fn pending_deposits_settle() {
+        let (booking_repo, account_repo) = new_booking_account_repo_pair();
+        let mut booking_repo = booking_repo.with_policy(LedgerPolicy{pending_deposits: true, settlement_delay: Some(3), ..LedgerPolicy::default()});
+        let txs = vec![
+            (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+            (Tx::new(2, 1, TxType::Deposit, Some(Amount::from(5_0000))), true),
+            (Tx::new(3, 1, TxType::Withdrawal, Some(Amount::from(1_0000))), false),
+            (Tx::new(1, 1, TxType::Dispute, None), false),
+            (Tx::new(2, 1, TxType::Settle, None), true),
+            (Tx::new(4, 1, TxType::Withdrawal, Some(Amount::from(1_0000))), true),
+            (Tx::new(5, 1, TxType::Deposit, Some(Amount::from(2_0000))), true),
+            (Tx::new(5, 1, TxType::RejectSettlement, None), true),
+            (Tx::new(5, 1, TxType::Settle, None), false),
+        ];
+        for (tx, should_succeed) in txs {
+            let tx_id = tx.tx_id;
+            assert_eq!(should_succeed, booking_repo.process_tx(tx).await.is_ok(), "tx_id: {}", tx_id);
+        }
+
+        let accounts = account_repo.lock().await.dump_accounts().await.unwrap();
+        assert_eq!(vec![summary(1, 14_0000, 0_0000, 14_0000, false)], accounts);
+
+        // Deposit 1 settled by itself before the fifth transaction.
+        let settlements: Vec<(u64, u32)> = booking_repo.dump_history().await.unwrap().iter()
+            .filter(|e| e.tx_type == TxType::Settle)
+            .map(|e| (e.seq, e.tx))
+            .collect();
+        assert_eq!(vec![(5, 1), (5, 2)], settlements);
+    }
+
+    #[tokio::test]
+    async fn pending_deposits_are_reported() {
+        let (booking_repo, account_repo) = new_booking_account_repo_pair();
+        let mut booking_repo = booking_repo.with_policy(LedgerPolicy{pending_deposits: true, ..LedgerPolicy::default()});
+        booking_repo.process_tx(Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000)))).await.unwrap();
+
+        let accounts = account_repo.lock().await.dump_accounts().await.unwrap();
+        assert_eq!(vec![AccountSummary{pending: Amount::from(10_0000), ..summary(1, 0_0000, 0_0000, 10_0000, false)}], accounts);
+    }
+
+    #[tokio::test]
+    async fn withdrawal_disputes_by_policy() {
+        let cases = vec![
+            ("withdrawal_not_disputable", false, vec![
+                (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                (Tx::new(2, 1, TxType::Withdrawal, Some(Amount::from(4_0000))), true),
+                (Tx::new(2, 1, TxType::Dispute, None), false),
+            ], summary(1, 6_0000, 0_0000, 6_0000, false)),
+            ("disputed_withdrawal", true, vec![
+                (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                (Tx::new(2, 1, TxType::Withdrawal, Some(Amount::from(4_0000))), true),
+                (Tx::new(2, 1, TxType::Dispute, None), true),
+            ], summary(1, 6_0000, 4_0000, 10_0000, false)),
+            ("resolved_withdrawal", true, vec![
+                (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                (Tx::new(2, 1, TxType::Withdrawal, Some(Amount::from(4_0000))), true),
+                (Tx::new(2, 1, TxType::Dispute, None), true),
+                (Tx::new(2, 1, TxType::Resolve, None), true),
+            ], summary(1, 6_0000, 0_0000, 6_0000, false)),
+            ("charged_back_withdrawal", true, vec![
+                (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                (Tx::new(2, 1, TxType::Withdrawal, Some(Amount::from(4_0000))), true),
+                (Tx{dispute: Some(1), ..Tx::new(2, 1, TxType::Dispute, Some(Amount::from(1_0000)))}, true),
+                (Tx{dispute: Some(1), ..Tx::new(2, 1, TxType::Chargeback, None)}, true),
+                (Tx::new(3, 1, TxType::Withdrawal, Some(Amount::from(7_0000))), true),
+            ], summary(1, 0_0000, 0_0000, 0_0000, false)),
+        ];
+
+        for (title, disputable_withdrawals, txs, expected) in cases {
+            let (booking_repo, account_repo) = new_booking_account_repo_pair();
+            let mut booking_repo = booking_repo.with_policy(LedgerPolicy{disputable_withdrawals, ..LedgerPolicy::default()});
+            for (tx, should_succeed) in txs {
+                let tx_id = tx.tx_id;
+                assert_eq!(should_succeed, booking_repo.process_tx(tx).await.is_ok(), "{}: tx_id: {}", title, tx_id);
+            }
+
+            let accounts = account_repo.lock().await.dump_accounts().await.unwrap();
+            assert_eq!(vec![expected], accounts, "{}", title);
+        }
+    }
+
+    #[tokio::test]
+    async fn assumptions_by_policy() {
+        let strict = LedgerPolicy{allow_negative_chargebacks: false, ..LedgerPolicy::default()};
+        let lenient = LedgerPolicy{allow_negative_amounts: true, truncate_excess_precision: true, ..LedgerPolicy::default()};
+        let cases = vec![
+            ("negative_chargeback", LedgerPolicy::default(), vec![
+                (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                (Tx::new(2, 1, TxType::Withdrawal, Some(Amount::from(8_0000))), true),
+                (Tx::new(1, 1, TxType::Dispute, None), true),
+                (Tx::new(1, 1, TxType::Chargeback, None), true),
+            ], summary(1, -8_0000, 0_0000, -8_0000, true)),
+            ("negative_chargeback_refused", strict.clone(), vec![
+                (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                (Tx::new(2, 1, TxType::Withdrawal, Some(Amount::from(8_0000))), true),
+                (Tx::new(1, 1, TxType::Dispute, None), true),
+                (Tx::new(1, 1, TxType::Chargeback, None), false),
+            ], summary(1, -8_0000, 10_0000, 2_0000, false)),
+            ("covered_chargeback", strict, vec![
+                (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                (Tx::new(1, 1, TxType::Dispute, None), true),
+                (Tx::new(1, 1, TxType::Chargeback, None), true),
+            ], summary(1, 0_0000, 0_0000, 0_0000, true)),
+            ("negative_amount_refused", LedgerPolicy::default(), vec![
+                (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                (Tx::new(2, 1, TxType::Deposit, Some(Amount::from(-1_0000))), false),
+            ], summary(1, 10_0000, 0_0000, 10_0000, false)),
+            ("negative_amount", lenient.clone(), vec![
+                (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                (Tx::new(2, 1, TxType::Deposit, Some(Amount::from(-1_0000))), true),
+                (Tx::new(3, 1, TxType::Withdrawal, Some(Amount::from(-5000))), true),
+            ], summary(1, 9_5000, 0_0000, 9_5000, false)),
+            ("negative_amount_only_for_deposits_and_withdrawals", lenient.clone(), vec![
+                (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                (Tx{to_client: Some(2), ..Tx::new(3, 1, TxType::Transfer, Some(Amount::from(-5_0000)))}, false),
+                (Tx::new(4, 1, TxType::Authorize, Some(Amount::from(-1_0000))), false),
+                (Tx{currency: Some(eur()), to_currency: Some(usd()), ..Tx::new(5, 1, TxType::Convert, Some(Amount::from(-1_0000)))}, false),
+                (Tx{reference: Some("CASE-1".into()), memo: Some("correction".into()), ..Tx::new(6, 1, TxType::AdjustmentCredit, Some(Amount::from(-1_0000)))}, false),
+                (Tx{reference: Some("CASE-2".into()), memo: Some("correction".into()), ..Tx::new(7, 1, TxType::AdjustmentDebit, Some(Amount::from(-1_0000)))}, false),
+            ], summary(1, 10_0000, 0_0000, 10_0000, false)),
+            ("excess_precision_refused", LedgerPolicy::default(), vec![
+                (Tx{currency: Some(usd()), ..Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000)))}, true),
+                (Tx{currency: Some(usd()), ..Tx::new(2, 1, TxType::Deposit, Some(Amount::from(1_2345)))}, false),
+            ], AccountSummary{currency: usd(), ..summary(1, 10_0000, 0_0000, 10_0000, false)}),
+            ("excess_precision_truncated", lenient, vec![
+                (Tx{currency: Some(usd()), ..Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000)))}, true),
+                (Tx{currency: Some(usd()), ..Tx::new(2, 1, TxType::Deposit, Some(Amount::from(1_2345)))}, true),
+                (Tx{currency: Some(usd()), ..Tx::new(2, 1, TxType::Dispute, Some(Amount::from(5999)))}, true),
+            ], AccountSummary{currency: usd(), ..summary(1, 10_6400, 5900, 11_2300, false)}),
+        ];
+
+        for (title, policy, txs, expected) in cases {
+            let (booking_repo, account_repo) = new_booking_account_repo_pair();
+            let mut booking_repo = booking_repo.with_policy(policy);
+            for (tx, should_succeed) in txs {
+                let tx_id = tx.tx_id;
+                assert_eq!(should_succeed, booking_repo.process_tx(tx).await.is_ok(), "{}: tx_id: {}", title, tx_id);
+            }
+
+            let accounts = account_repo.lock().await.dump_accounts().await.unwrap();
+            assert_eq!(vec![expected], accounts, "{}", title);
+        }
+    }
+
+    #[tokio::test]
+    async fn excess_precision_from_csv() {
+        let data = "type, client, tx, amount, currency
+deposit,    1,  1,    10.00005, USD
+deposit,    1,  2,    1.239999, USD
+withdrawal, 1,  3,    0.019999, USD";
+        let cases = vec![
+            ("refused", LedgerPolicy::default(), vec![false, false, false], None),
+            ("truncated", LedgerPolicy{truncate_excess_precision: true, ..LedgerPolicy::default()}, vec![true, true, true], Some(11_2200)),
+        ];
+
+        for (title, policy, results, expected) in cases {
+            let (booking_repo, account_repo) = new_booking_account_repo_pair();
+            let mut booking_repo = booking_repo.with_policy(policy);
+            let mut rdr = csv::ReaderBuilder::new()
+                .has_headers(true)
+                .trim(csv::Trim::All)
+                .from_reader(data.as_bytes());
+            for (record, should_succeed) in rdr.deserialize::<Tx>().zip(results) {
+                let tx = record.unwrap();
+                let tx_id = tx.tx_id;
+                assert_eq!(should_succeed, booking_repo.process_tx(tx).await.is_ok(), "{}: tx_id: {}", title, tx_id);
+            }
+
+            let total = account_repo.lock().await.dump_accounts().await.unwrap().first().map(|a| a.total.to_i64());
+            assert_eq!(expected, total, "{}", title);
+        }
+    }
+
+    #[tokio::test]
+    async fn credit_limits() {
+        let limit = |tx_id, client, amount| Tx{
+            operator: Some("alice".into()),
+            reason: Some(ReasonCode::CustomerRequest),
+            ..Tx::new(tx_id, client, TxType::CreditLimit, Some(Amount::from(amount)))
+        };
+        let cases = vec![
+            ("overdraft", LedgerPolicy::default(), vec![
+                (limit(1, 1, 5_0000), true),
+                (Tx::new(2, 1, TxType::Deposit, Some(Amount::from(2_0000))), true),
+                (Tx::new(3, 1, TxType::Withdrawal, Some(Amount::from(6_0000))), true),
+                (Tx::new(4, 1, TxType::Withdrawal, Some(Amount::from(1_0001))), false),
+            ], AccountSummary{credit_limit: 5_0000.into(), headroom: 1_0000.into(), ..summary(1, -4_0000, 0_0000, -4_0000, false)}),
+            ("limit_needs_operator", LedgerPolicy::default(), vec![
+                (Tx{reason: Some(ReasonCode::CustomerRequest), ..Tx::new(1, 1, TxType::CreditLimit, Some(Amount::from(5_0000)))}, false),
+                (Tx::new(2, 1, TxType::Deposit, Some(Amount::from(2_0000))), true),
+                (Tx::new(3, 1, TxType::Withdrawal, Some(Amount::from(6_0000))), false),
+            ], summary(1, 2_0000, 0_0000, 2_0000, false)),
+            ("negative_limit", LedgerPolicy::default(), vec![
+                (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(2_0000))), true),
+                (limit(2, 1, -1_0000), false),
+            ], summary(1, 2_0000, 0_0000, 2_0000, false)),
+            ("lowered_limit", LedgerPolicy::default(), vec![
+                (limit(1, 1, 5_0000), true),
+                (Tx::new(2, 1, TxType::Withdrawal, Some(Amount::from(4_0000))), true),
+                (limit(3, 1, 2_0000), true),
+                (Tx::new(4, 1, TxType::Withdrawal, Some(Amount::from(1))), false),
+            ], AccountSummary{credit_limit: 2_0000.into(), headroom: (-2_0000).into(), ..summary(1, -4_0000, 0_0000, -4_0000, false)}),
+            ("dispute_past_limit", LedgerPolicy::default(), vec![
+                (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(5_0000))), true),
+                (limit(2, 1, 1_0000), true),
+                (Tx::new(3, 1, TxType::Withdrawal, Some(Amount::from(5_0000))), true),
+                (Tx::new(1, 1, TxType::Dispute, None), true),
+            ], AccountSummary{credit_limit: 1_0000.into(), headroom: (-4_0000).into(), ..summary(1, -5_0000, 5_0000, 0_0000, false)}),
+            ("dispute_within_limit", LedgerPolicy{disputes_past_credit_limit: false, ..LedgerPolicy::default()}, vec![
+                (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(5_0000))), true),
+                (limit(2, 1, 1_0000), true),
+                (Tx::new(3, 1, TxType::Withdrawal, Some(Amount::from(5_0000))), true),
+                (Tx::new(1, 1, TxType::Dispute, None), false),
+                (Tx{dispute: Some(1), ..Tx::new(1, 1, TxType::Dispute, Some(Amount::from(1_0000)))}, true),
+            ], AccountSummary{credit_limit: 1_0000.into(), headroom: 0.into(), ..summary(1, -1_0000, 1_0000, 0_0000, false)}),
+        ];
+
+        for (title, policy, txs, expected) in cases {
+            let (booking_repo, account_repo) = new_booking_account_repo_pair();
+            let mut booking_repo = booking_repo.with_policy(policy);
+            for (tx, should_succeed) in txs {
+                let tx_id = tx.tx_id;
+                assert_eq!(should_succeed, booking_repo.process_tx(tx).await.is_ok(), "{}: tx_id: {}", title, tx_id);
+            }
+
+            let accounts = account_repo.lock().await.dump_accounts().await.unwrap();
+            assert_eq!(vec![expected], accounts, "{}", title);
+        }
+    }
+
+    #[tokio::test]
+    async fn credit_limits_from_config() {
+        let account_repo = Arc::new(Mutex::new(InMemoryAccountRepository::try_from(vec![
+            AccountSpec{client: 1, currency: Currency::default(), credit_limit: 3_0000.into(), tier: None},
+            AccountSpec{client: 2, currency: usd(), credit_limit: 1_0000.into(), tier: None},
+        ]).unwrap()));
+        let mut booking_repo = InMemoryBookingRepository::new(account_repo.clone());
+        booking_repo.process_tx(Tx::new(1, 1, TxType::Withdrawal, Some(Amount::from(3_0000)))).await.unwrap();
+        assert!(booking_repo.process_tx(Tx::new(2, 2, TxType::Withdrawal, Some(Amount::from(1_0000)))).await.is_err());
+
+        let mut accounts = account_repo.lock().await.dump_accounts().await.unwrap();
+        accounts.sort_by(summary_sort);
+        assert_eq!(vec![
+            AccountSummary{credit_limit: 3_0000.into(), headroom: 0.into(), ..summary(1, -3_0000, 0_0000, -3_0000, false)},
+            AccountSummary{currency: usd(), credit_limit: 1_0000.into(), headroom: 1_0000.into(), ..summary(2, 0_0000, 0_0000, 0_0000, false)},
+        ], accounts);
+
+        assert!(InMemoryAccountRepository::try_from(vec![
+            AccountSpec{client: 1, currency: Currency::default(), credit_limit: (-1).into(), tier: None},
+        ]).is_err());
+    }
+
+    #[tokio::test]
+    async fn withdrawal_limits() {
+        let account_repo = Arc::new(Mutex::new(InMemoryAccountRepository::try_from(vec![
+            AccountSpec{client: 2, currency: Currency::default(), credit_limit: Amount::ZERO, tier: Some("gold".into())},
+        ]).unwrap()));
+        let limits = LimitTable::from_specs([
+            LimitSpec{tier: "".into(), currency: None, max_withdrawal: Some(5_0000.into()), max_count: Some(2), max_total: None, window: 3, window_unit: WindowUnit::Transactions},
+            LimitSpec{tier: "gold".into(), currency: None, max_withdrawal: None, max_count: None, max_total: Some(10_0000.into()), window: 60, window_unit: WindowUnit::Seconds},
+        ]);
+        let mut booking_repo = InMemoryBookingRepository::new(account_repo.clone()).with_limits(limits);
+        let at = |tx: Tx, timestamp| Tx{timestamp: Some(timestamp), ..tx};
+        let txs = vec![
+            (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(20_0000))), true),
+            (Tx::new(2, 1, TxType::Withdrawal, Some(Amount::from(5_0001))), false),
+            (Tx::new(3, 1, TxType::Withdrawal, Some(Amount::from(5_0000))), true),
+            (Tx::new(4, 1, TxType::Withdrawal, Some(Amount::from(1_0000))), true),
+            (Tx::new(5, 1, TxType::Withdrawal, Some(Amount::from(1_0000))), false),
+            // tx 3 is out of the window
+            (Tx::new(6, 1, TxType::Withdrawal, Some(Amount::from(1_0000))), true),
+            (Tx::new(7, 2, TxType::Deposit, Some(Amount::from(30_0000))), true),
+            (at(Tx::new(8, 2, TxType::Withdrawal, Some(Amount::from(6_0000))), 1_000), true),
+            (at(Tx::new(9, 2, TxType::Withdrawal, Some(Amount::from(4_0001))), 1_030), false),
+            (Tx::new(10, 2, TxType::Withdrawal, Some(Amount::from(1_0000))), false),
+            (at(Tx::new(11, 2, TxType::Withdrawal, Some(Amount::from(10_0000))), 1_060), true),
+        ];
+        for (tx, should_succeed) in txs {
+            let tx_id = tx.tx_id;
+            assert_eq!(should_succeed, booking_repo.process_tx(tx).await.is_ok(), "tx_id: {}", tx_id);
+        }
+
+        let err = booking_repo.process_tx(Tx::new(12, 1, TxType::Withdrawal, Some(Amount::from(6_0000)))).await.unwrap_err();
+        assert!(matches!(err.kind(), LedgerErrorKind::LimitExceeded(_)));
+        assert_eq!(vec![
+            LimitViolation{client: 1, tier: "".into(), limit: LimitKind::MaxWithdrawal, count: 2},
+            LimitViolation{client: 1, tier: "".into(), limit: LimitKind::MaxCount, count: 1},
+            LimitViolation{client: 2, tier: "gold".into(), limit: LimitKind::MaxTotal, count: 1},
+        ], booking_repo.dump_violations().await.unwrap());
+
+        let mut accounts = account_repo.lock().await.dump_accounts().await.unwrap();
+        accounts.sort_by(summary_sort);
+        assert_eq!(vec![
+            summary(1, 13_0000, 0_0000, 13_0000, false),
+            summary(2, 14_0000, 0_0000, 14_0000, false),
+        ], accounts);
+    }
+
+    #[tokio::test]
+    async fn negative_amounts_are_checked_the_way_funds_move() {
+        let (booking_repo, account_repo) = new_booking_account_repo_pair();
+        let limits = LimitTable::from_specs([
+            LimitSpec{tier: "".into(), currency: None, max_withdrawal: None, max_count: None, max_total: Some(10_0000.into()), window: 20, window_unit: WindowUnit::Transactions},
+        ]);
+        let mut booking_repo = booking_repo
+            .with_limits(limits)
+            .with_policy(LedgerPolicy{allow_negative_amounts: true, ..LedgerPolicy::default()});
+        let txs = vec![
+            (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(20_0000))), true),
+            (Tx::new(2, 1, TxType::Withdrawal, Some(Amount::from(8_0000))), true),
+            // A negative withdrawal doesn't lower the withdrawals in the window.
+            (Tx::new(3, 1, TxType::Withdrawal, Some(Amount::from(-5_0000))), true),
+            (Tx::new(4, 1, TxType::Withdrawal, Some(Amount::from(5_0000))), false),
+            // A negative deposit needs the funds and counts as a withdrawal.
+            (Tx::new(5, 2, TxType::Deposit, Some(Amount::from(1_0000))), true),
+            (Tx::new(10, 2, TxType::Deposit, Some(Amount::from(-3_0000))), false),
+            (Tx::new(6, 1, TxType::Deposit, Some(Amount::from(-2_0000))), true),
+            (Tx::new(7, 1, TxType::Deposit, Some(Amount::from(-1_0000))), false),
+            (Tx::new(6, 1, TxType::Dispute, None), false),
+            (Tx{
+                operator: Some("alice".into()),
+                reason: Some(ReasonCode::OperationalError),
+                status: Some(AccountStatus::DepositsFrozen),
+                ..Tx::new(8, 1, TxType::SetStatus, None)
+            }, true),
+            (Tx::new(9, 1, TxType::Withdrawal, Some(Amount::from(-1_0000))), false),
+        ];
+        for (tx, should_succeed) in txs {
+            let tx_id = tx.tx_id;
+            assert_eq!(should_succeed, booking_repo.process_tx(tx).await.is_ok(), "tx_id: {}", tx_id);
+        }
+
+        assert_eq!(vec![
+            LimitViolation{client: 1, tier: "".into(), limit: LimitKind::MaxTotal, count: 2},
+        ], booking_repo.dump_violations().await.unwrap());
+        let mut accounts = account_repo.lock().await.dump_accounts().await.unwrap();
+        accounts.sort_by(summary_sort);
+        assert_eq!(vec![
+            AccountSummary{status: AccountStatus::DepositsFrozen, ..summary(1, 15_0000, 0_0000, 15_0000, false)},
+            summary(2, 1_0000, 0_0000, 1_0000, false),
+        ], accounts);
+        assert_books_mirror_accounts(&booking_repo, &accounts).await;
+    }
+
+    #[tokio::test]
+    async fn transfers_and_captures_count_towards_withdrawal_limits() {
+        let (booking_repo, account_repo) = new_booking_account_repo_pair();
+        let limits = LimitTable::from_specs([
+            LimitSpec{tier: "".into(), currency: None, max_withdrawal: Some(5_0000.into()), max_count: Some(2), max_total: None, window: 10, window_unit: WindowUnit::Transactions},
+        ]);
+        let mut booking_repo = booking_repo.with_limits(limits);
+        let txs = vec![
+            (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(20_0000))), true),
+            (Tx{to_client: Some(3), ..Tx::new(2, 1, TxType::Transfer, Some(Amount::from(6_0000)))}, false),
+            (Tx{to_client: Some(3), ..Tx::new(3, 1, TxType::Transfer, Some(Amount::from(4_0000)))}, true),
+            (Tx::new(4, 1, TxType::Authorize, Some(Amount::from(5_0000))), true),
+            (Tx::new(4, 1, TxType::Capture, None), true),
+            // The transfer and the capture used up the count.
+            (Tx::new(5, 1, TxType::Withdrawal, Some(Amount::from(1_0000))), false),
+            (Tx::new(6, 1, TxType::Authorize, Some(Amount::from(6_0000))), true),
+            (Tx::new(6, 1, TxType::Capture, None), false),
+            (Tx::new(6, 1, TxType::Void, None), true),
+        ];
+        for (tx, should_succeed) in txs {
+            let tx_id = tx.tx_id;
+            assert_eq!(should_succeed, booking_repo.process_tx(tx).await.is_ok(), "tx_id: {}", tx_id);
+        }
+
+        assert_eq!(vec![
+            LimitViolation{client: 1, tier: "".into(), limit: LimitKind::MaxWithdrawal, count: 2},
+            LimitViolation{client: 1, tier: "".into(), limit: LimitKind::MaxCount, count: 1},
+        ], booking_repo.dump_violations().await.unwrap());
+
+        let mut accounts = account_repo.lock().await.dump_accounts().await.unwrap();
+        accounts.sort_by(summary_sort);
+        assert_eq!(vec![
+            summary(1, 11_0000, 0_0000, 11_0000, false),
+            summary(3, 4_0000, 0_0000, 4_0000, false),
+        ], accounts);
+    }
+
+    #[tokio::test]
+    async fn reserved_funds_are_voided_on_locked_accounts() {
+        let (mut booking_repo, account_repo) = new_booking_account_repo_pair();
+        let txs = vec![
+            (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+            (Tx::new(2, 1, TxType::Deposit, Some(Amount::from(5_0000))), true),
+            (Tx::new(3, 1, TxType::Authorize, Some(Amount::from(4_0000))), true),
+            (Tx::new(2, 1, TxType::Dispute, None), true),
+            (Tx::new(2, 1, TxType::Chargeback, None), true),
+            (Tx::new(4, 1, TxType::Authorize, Some(Amount::from(1_0000))), false),
+            (Tx::new(3, 1, TxType::Void, None), true),
+        ];
+        for (tx, should_succeed) in txs {
+            let tx_id = tx.tx_id;
+            assert_eq!(should_succeed, booking_repo.process_tx(tx).await.is_ok(), "tx_id: {}", tx_id);
+        }
+
+        let accounts = account_repo.lock().await.dump_accounts().await.unwrap();
+        assert_eq!(vec![
+            AccountSummary{status: AccountStatus::ChargebackLocked, ..summary(1, 10_0000, 0_0000, 10_0000, true)},
+        ], accounts);
+    }
+
+    #[tokio::test]
+    async fn risk_rules() {
+        let rule = |id: &str, kind, threshold, amount: Option<i64>, window, action| RiskRule{
+            id: id.into(),
+            kind,
+            threshold,
+            amount: amount.map(Amount::from),
+            window,
+            action,
+        };
+        let rules = RiskRules::from_specs([
+            rule("disputes", RiskRuleKind::DisputeRatio, Some(50), None, None, RiskAction::Flag),
+            rule("many_disputes", RiskRuleKind::DisputeRatio, Some(75), None, None, RiskAction::Lock),
+            rule("cash_out", RiskRuleKind::WithdrawalAfterDeposit, None, Some(100_0000), Some(1), RiskAction::Freeze),
+        ]);
+        let (booking_repo, account_repo) = new_booking_account_repo_pair();
+        let mut booking_repo = booking_repo.with_risk_rules(rules);
+        let txs = vec![
+            (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+            (Tx::new(2, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+            (Tx::new(1, 1, TxType::Dispute, None), true),
+            (Tx::new(2, 1, TxType::Dispute, None), true),
+            (Tx::new(3, 1, TxType::Deposit, Some(Amount::from(1_0000))), false),
+            (Tx::new(4, 2, TxType::Deposit, Some(Amount::from(100_0000))), true),
+            (Tx{timestamp: Some(1_700_000_000), ..Tx::new(5, 2, TxType::Withdrawal, Some(Amount::from(50_0000)))}, true),
+            (Tx::new(6, 2, TxType::Withdrawal, Some(Amount::from(1_0000))), false),
+            (Tx::new(7, 2, TxType::Deposit, Some(Amount::from(1_0000))), true),
+            (Tx{operator: Some("alice".into()), reason: Some(ReasonCode::FraudCleared), ..Tx::new(8, 2, TxType::Unlock, None)}, true),
+            (Tx::new(9, 2, TxType::Withdrawal, Some(Amount::from(10_0000))), true),
+            // An outgoing transfer cashes out like a withdrawal.
+            (Tx::new(10, 3, TxType::Deposit, Some(Amount::from(100_0000))), true),
+            (Tx{to_client: Some(2), ..Tx::new(11, 3, TxType::Transfer, Some(Amount::from(20_0000)))}, true),
+            (Tx::new(12, 3, TxType::Withdrawal, Some(Amount::from(1_0000))), false),
+        ];
+        for (tx, should_succeed) in txs {
+            let tx_id = tx.tx_id;
+            assert_eq!(should_succeed, booking_repo.process_tx(tx).await.is_ok(), "tx_id: {}", tx_id);
+        }
+
+        assert_eq!(vec![
+            RiskEvent{seq: 4, tx: 2, client: 1, rule: "disputes".into(), action: RiskAction::Flag, timestamp: None},
+            RiskEvent{seq: 4, tx: 2, client: 1, rule: "many_disputes".into(), action: RiskAction::Lock, timestamp: None},
+            RiskEvent{seq: 7, tx: 5, client: 2, rule: "cash_out".into(), action: RiskAction::Freeze, timestamp: Some(1_700_000_000)},
+            RiskEvent{seq: 13, tx: 11, client: 3, rule: "cash_out".into(), action: RiskAction::Freeze, timestamp: None},
+        ], booking_repo.dump_risk_events().await.unwrap());
+
+        let mut accounts = account_repo.lock().await.dump_accounts().await.unwrap();
+        accounts.sort_by(summary_sort);
+        assert_eq!(vec![
+            AccountSummary{status: AccountStatus::FullyFrozen, ..summary(1, 0_0000, 20_0000, 20_0000, true)},
+            summary(2, 61_0000, 0_0000, 61_0000, false),
+            AccountSummary{status: AccountStatus::WithdrawalsFrozen, ..summary(3, 80_0000, 0_0000, 80_0000, false)},
+        ], accounts);
+    }
+
+    #[tokio::test]
+    async fn account_statuses() {
+        let status = |tx_id, client, status| Tx{
+            operator: Some("alice".into()),
+            reason: Some(ReasonCode::OperationalError),
+            status: Some(status),
+            ..Tx::new(tx_id, client, TxType::SetStatus, None)
+        };
+        let cases = vec![
+            ("withdrawals_frozen", vec![
+                (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                (status(2, 1, AccountStatus::WithdrawalsFrozen), true),
+                (Tx::new(3, 1, TxType::Withdrawal, Some(Amount::from(1_0000))), false),
+                (Tx{to_client: Some(2), ..Tx::new(4, 1, TxType::Transfer, Some(Amount::from(1_0000)))}, false),
+                (Tx::new(5, 1, TxType::Deposit, Some(Amount::from(1_0000))), true),
+                (Tx::new(1, 1, TxType::Dispute, None), true),
+            ], AccountSummary{status: AccountStatus::WithdrawalsFrozen, ..summary(1, 1_0000, 10_0000, 11_0000, false)}),
+            ("deposits_frozen", vec![
+                (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                (status(2, 1, AccountStatus::DepositsFrozen), true),
+                (Tx::new(3, 1, TxType::Deposit, Some(Amount::from(1_0000))), false),
+                (Tx::new(4, 1, TxType::Withdrawal, Some(Amount::from(1_0000))), true),
+            ], AccountSummary{status: AccountStatus::DepositsFrozen, ..summary(1, 9_0000, 0_0000, 9_0000, false)}),
+            ("fully_frozen", vec![
+                (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                (status(2, 1, AccountStatus::FullyFrozen), true),
+                (Tx::new(3, 1, TxType::Deposit, Some(Amount::from(1_0000))), false),
+                (Tx::new(1, 1, TxType::Dispute, None), false),
+            ], AccountSummary{status: AccountStatus::FullyFrozen, ..summary(1, 10_0000, 0_0000, 10_0000, true)}),
+            ("reactivated", vec![
+                (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                (Tx::new(2, 1, TxType::Withdrawal, Some(Amount::from(10_0000))), true),
+                (status(3, 1, AccountStatus::Closed), false),
+                (Tx::new(4, 1, TxType::Close, None), true),
+                (Tx::new(5, 1, TxType::Deposit, Some(Amount::from(1_0000))), false),
+                (status(6, 1, AccountStatus::Active), true),
+                (Tx::new(7, 1, TxType::Deposit, Some(Amount::from(1_0000))), true),
+            ], summary(1, 1_0000, 0_0000, 1_0000, false)),
+            ("status_needs_operator", vec![
+                (Tx{operator: None, ..status(1, 1, AccountStatus::FullyFrozen)}, false),
+                (Tx{status: None, ..status(2, 1, AccountStatus::FullyFrozen)}, false),
+                (Tx::new(3, 1, TxType::Deposit, Some(Amount::from(1_0000))), true),
+            ], summary(1, 1_0000, 0_0000, 1_0000, false)),
+            ("chargeback_locked", vec![
+                (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                (status(2, 1, AccountStatus::WithdrawalsFrozen), true),
+                (Tx::new(1, 1, TxType::Dispute, None), true),
+                (Tx::new(1, 1, TxType::Chargeback, None), true),
+                (Tx::new(3, 1, TxType::Deposit, Some(Amount::from(1_0000))), false),
+                (Tx::new(1, 1, TxType::Representment, None), true),
+            ], summary(1, 0_0000, 0_0000, 0_0000, true)),
+        ];
+
+        for (title, txs, expected) in cases {
+            let (mut booking_repo, account_repo) = new_booking_account_repo_pair();
+            for (tx, should_succeed) in txs {
+                let tx_id = tx.tx_id;
+                assert_eq!(should_succeed, booking_repo.process_tx(tx).await.is_ok(), "{}: tx_id: {}", title, tx_id);
+            }
+
+            let accounts = account_repo.lock().await.dump_accounts().await.unwrap();
+            assert_eq!(vec![expected], accounts, "{}", title);
+        }
+    }
+
+    #[tokio::test]
+    async fn status_change_is_recorded() {
+        let (mut booking_repo, account_repo) = new_booking_account_repo_pair();
+        let txs = vec![
+            Tx::new(1, 1, TxType::Deposit, Some(Amount::from(1_0000))),
+            Tx{operator: Some("bob".into()), reason: Some(ReasonCode::CustomerRequest), status: Some(AccountStatus::DepositsFrozen), reference: Some("CASE-7".into()), ..Tx::new(2, 1, TxType::SetStatus, None)},
+        ];
+        for tx in txs {
+            booking_repo.process_tx(tx).await.unwrap();
+        }
+
+        let changes = account_repo.lock().await.dump_status_changes().await.unwrap();
+        assert_eq!(vec![
+            StatusChange{client: 1, operator: "bob".into(), reason: ReasonCode::CustomerRequest, seq: 2, from: AccountStatus::Active, to: AccountStatus::DepositsFrozen, timestamp: None, reference: Some("CASE-7".into()), memo: None},
+        ], changes);
+    }
+
+    #[tokio::test]
+    async fn account_lifecycle() {
+        let strict = LedgerPolicy{strict_accounts: true, ..LedgerPolicy::default()};
+        let cases = vec![
+            ("implicit_account", LedgerPolicy::default(), vec![
+                (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                (Tx::new(1, 1, TxType::Open, None), false),
+            ], vec![summary(1, 10_0000, 0_0000, 10_0000, false)]),
+            ("strict_needs_open", strict.clone(), vec![
+                (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), false),
+                (Tx::new(2, 1, TxType::Open, None), true),
+                (Tx::new(3, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                (Tx{to_client: Some(2), ..Tx::new(4, 1, TxType::Transfer, Some(Amount::from(1_0000)))}, false),
+                (Tx::new(3, 2, TxType::Resolve, None), false),
+            ], vec![summary(1, 10_0000, 0_0000, 10_0000, false)]),
+            ("close_with_balance", strict.clone(), vec![
+                (Tx::new(1, 1, TxType::Open, None), true),
+                (Tx::new(2, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                (Tx::new(3, 1, TxType::Close, None), false),
+            ], vec![summary(1, 10_0000, 0_0000, 10_0000, false)]),
+            ("close_with_open_dispute", LedgerPolicy{disputable_withdrawals: true, ..strict.clone()}, vec![
+                (Tx::new(1, 1, TxType::Open, None), true),
+                (Tx::new(2, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+                (Tx::new(3, 1, TxType::Withdrawal, Some(Amount::from(10_0000))), true),
+                (Tx::new(3, 1, TxType::Dispute, None), true),
+                (Tx::new(4, 1, TxType::Close, None), false),
+                (Tx::new(3, 1, TxType::Resolve, None), true),
+                (Tx::new(4, 1, TxType::Close, None), true),
+            ], vec![AccountSummary{status: AccountStatus::Closed, ..summary(1, 0_0000, 0_0000, 0_0000, true)}]),
+            ("closed_account", strict, vec![
+                (Tx::new(1, 1, TxType::Open, None), true),
+                (Tx::new(2, 1, TxType::Close, None), true),
+                (Tx::new(3, 1, TxType::Deposit, Some(Amount::from(1_0000))), false),
+                (Tx::new(4, 1, TxType::Close, None), false),
+                (Tx::new(5, 1, TxType::Open, None), false),
+                (Tx{operator: Some("alice".into()), reason: Some(ReasonCode::CustomerRequest), ..Tx::new(6, 1, TxType::Unlock, None)}, false),
+            ], vec![AccountSummary{status: AccountStatus::Closed, ..summary(1, 0_0000, 0_0000, 0_0000, true)}]),
+        ];
+
+        for (title, policy, txs, expected) in cases {
+            let (booking_repo, account_repo) = new_booking_account_repo_pair();
+            let mut booking_repo = booking_repo.with_policy(policy);
+            for (tx, should_succeed) in txs {
+                let tx_id = tx.tx_id;
+                assert_eq!(should_succeed, booking_repo.process_tx(tx).await.is_ok(), "{}: tx_id: {}", title, tx_id);
+            }
+
+            let accounts = account_repo.lock().await.dump_accounts().await.unwrap();
+            assert_eq!(expected, accounts, "{}", title);
+        }
+    }
+
+    #[tokio::test]
+    async fn registered_accounts() {
+        let account_repo = Arc::new(Mutex::new(InMemoryAccountRepository::try_from(vec![
+            AccountSpec{client: 1, currency: Currency::default(), credit_limit: Amount::ZERO, tier: None},
+        ]).unwrap()));
+        let mut booking_repo = InMemoryBookingRepository::new(account_repo.clone())
+            .with_policy(LedgerPolicy{strict_accounts: true, ..LedgerPolicy::default()});
+        booking_repo.process_tx(Tx::new(1, 1, TxType::Deposit, Some(Amount::from(1_0000)))).await.unwrap();
+        assert!(booking_repo.process_tx(Tx::new(2, 2, TxType::Deposit, Some(Amount::from(1_0000)))).await.is_err());
+
+        let accounts = account_repo.lock().await.dump_accounts().await.unwrap();
+        assert_eq!(vec![summary(1, 1_0000, 0_0000, 1_0000, false)], accounts);
+    }
+
+    #[tokio::test]
+    async fn common_cases()  {
+        let cases = CommonCases::cases();
+        for (title, mut case) in cases.into_iter() {
+            let (mut booking_repo, account_repo) = new_booking_account_repo_pair();
+
+            for (tx, should_succeed) in case.txs {
+                let tx_id = tx.tx_id;
+                let res = booking_repo.process_tx(tx).await;
+                assert!(should_succeed == res.is_ok(), "{}: tx_id: {}", title, tx_id);
+            }
+
+            let mut accounts = account_repo.lock().await.dump_accounts().await.unwrap();
+            case.expected.sort_by(summary_sort);
+            accounts.sort_by(summary_sort);
+            assert_eq!(case.expected, accounts, "{}", title);
+            assert_books_mirror_accounts(&booking_repo, &accounts).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn journal_is_balanced() {
+        let (booking_repo, account_repo) = new_booking_account_repo_pair();
+        let fees = FeeSchedule::from_specs([
+            FeeSpec{tx_type: TxType::Withdrawal, currency: None, flat: Some(Amount::from(5000)), rate: None},
+        ], RoundingMode::Down);
+        let interest = InterestTable::from_specs([
+            InterestSpec{currency: None, from: Amount::ZERO, rate: "0.01".parse().unwrap()},
+        ], RoundingMode::Down);
+        let policy = LedgerPolicy{disputable_withdrawals: true, pending_deposits: true, ..LedgerPolicy::default()};
+        let mut booking_repo = booking_repo.with_fees(fees).with_interest(interest).with_policy(policy);
+        let txs = vec![
+            (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(20_0000))), true),
+            (Tx::new(1, 1, TxType::Settle, None), true),
+            (Tx::new(2, 1, TxType::Withdrawal, Some(Amount::from(4_0000))), true),
+            (Tx::new(2, 1, TxType::Dispute, None), true),
+            (Tx::new(2, 1, TxType::Chargeback, None), true),
+            (Tx{to_client: Some(2), ..Tx::new(3, 1, TxType::Transfer, Some(Amount::from(5_0000)))}, true),
+            (Tx::new(3, 1, TxType::Dispute, None), true),
+            (Tx::new(3, 1, TxType::Chargeback, None), true),
+            (Tx::new(3, 2, TxType::Withdrawal, Some(Amount::from(1_0000))), false),
+            (Tx{currency: Some(eur()), ..Tx::new(4, 4, TxType::Deposit, Some(Amount::from(10_0000)))}, true),
+            (Tx{currency: Some(eur()), ..Tx::new(4, 4, TxType::Settle, None)}, true),
+            (Tx{currency: Some(eur()), to_currency: Some(usd()), ..Tx::new(5, 4, TxType::Convert, Some(Amount::from(5_5500)))}, true),
+            (Tx{currency: Some(usd()), ..Tx::new(6, 4, TxType::Authorize, Some(Amount::from(3_0000)))}, true),
+            (Tx{currency: Some(usd()), ..Tx::new(6, 4, TxType::Capture, Some(Amount::from(2_0000)))}, true),
+            (Tx::new(7, 3, TxType::Deposit, Some(Amount::from(3_0000))), true),
+            (Tx::new(7, 3, TxType::Settle, None), true),
+            (Tx::new(8, 3, TxType::Withdrawal, Some(Amount::from(2_0000))), true),
+            (Tx::new(7, 3, TxType::Dispute, None), true),
+            (Tx::new(7, 3, TxType::Chargeback, None), true),
+            (Tx{period: Some("2024-01".into()), ..Tx::new(9, 1, TxType::Accrue, None)}, true),
+            (Tx::new(10, 1, TxType::Deposit, Some(Amount::from(1_0000))), true),
+            (Tx::new(10, 1, TxType::RejectSettlement, None), true),
+        ];
+        for (tx, should_succeed) in txs {
+            let tx_id = tx.tx_id;
+            assert_eq!(should_succeed, booking_repo.process_tx(tx).await.is_ok(), "tx_id: {}", tx_id);
+        }
+
+        let journal = booking_repo.dump_journal().await.unwrap();
+        let debits = journal.iter().fold(Amount::ZERO, |sum, e| (sum + e.debit).unwrap());
+        let credits = journal.iter().fold(Amount::ZERO, |sum, e| (sum + e.credit).unwrap());
+        assert_eq!(debits, credits);
+
+        let accounts = account_repo.lock().await.dump_accounts().await.unwrap();
+        assert_books_mirror_accounts(&booking_repo, &accounts).await;
+
+        // The bank saw 20 + 3 in and 4 + 2 out, 4 back from the claim and
+        // 3 back to the card network, in the default currency.
+        let house: Vec<(JournalAccount, Amount)> = booking_repo.trial_balance().await.unwrap().into_iter()
+            .filter(|r| r.currency == Currency::default() && r.client.is_none())
+            .map(|r| (r.account, r.balance))
+            .collect();
+        assert_eq!(vec![
+            (JournalAccount::SettlementCash, Amount::from(18_0000)),
+            (JournalAccount::ChargebackLosses, Amount::ZERO),
+            (JournalAccount::FeeIncome, Amount::from(-1_0000)),
+            (JournalAccount::InterestExpense, Amount::from(1950)),
+        ], house);
+    }
+
+    #[tokio::test]
+    async fn exposure_report() {
+        let (booking_repo, account_repo) = new_booking_account_repo_pair();
+        let booking_repo = booking_repo.with_policy(LedgerPolicy{
+            unlock_on_chargeback_reversal: true,
+            disputable_withdrawals: true,
+            ..LedgerPolicy::default()
+        });
+        let ledger = Ledger::new(account_repo, Arc::new(Mutex::new(booking_repo)));
+        let txs = vec![
+            (Tx::new(1, 1, TxType::Deposit, Some(Amount::from(10_0000))), true),
+            (Tx::new(2, 1, TxType::Deposit, Some(Amount::from(11_0000))), true),
+            (Tx::new(3, 1, TxType::Withdrawal, Some(Amount::from(20_0000))), true),
+            (Tx::new(2, 1, TxType::Dispute, None), true),
+            (Tx::new(2, 1, TxType::Chargeback, None), true),
+            (Tx::new(4, 2, TxType::Deposit, Some(Amount::from(5_0000))), true),
+            (Tx{dispute: Some(1), ..Tx::new(4, 2, TxType::Dispute, Some(Amount::from(1_0000)))}, true),
+            (Tx{dispute: Some(1), ..Tx::new(4, 2, TxType::Chargeback, None)}, true),
+            (Tx{dispute: Some(1), ..Tx::new(4, 2, TxType::ChargebackReversal, None)}, true),
+            (Tx{dispute: Some(2), ..Tx::new(4, 2, TxType::Dispute, Some(Amount::from(2_0000)))}, true),
+            (Tx{currency: Some(usd()), ..Tx::new(5, 3, TxType::Deposit, Some(Amount::from(7_0000)))}, true),
+            (Tx{currency: Some(usd()), ..Tx::new(5, 3, TxType::Dispute, Some(Amount::from(3_0000)))}, true),
+            (Tx{currency: Some(usd()), ..Tx::new(5, 3, TxType::Chargeback, None)}, true),
+            (Tx{currency: Some(usd()), ..Tx::new(5, 3, TxType::Representment, None)}, true),
+            // The transfer is charged back to its receiver, the withdrawal
+            // charged back is a claim paid to client 6.
+            (Tx::new(6, 6, TxType::Deposit, Some(Amount::from(6_0000))), true),
+            (Tx{to_client: Some(7), ..Tx::new(7, 6, TxType::Transfer, Some(Amount::from(4_0000)))}, true),
+            (Tx::new(8, 7, TxType::Withdrawal, Some(Amount::from(3_0000))), true),
+            (Tx{currency: Some(usd()), ..Tx::new(9, 7, TxType::Deposit, Some(Amount::from(2_0000)))}, true),
+            (Tx::new(10, 6, TxType::Withdrawal, Some(Amount::from(1_0000))), true),
+            (Tx::new(10, 6, TxType::Dispute, None), true),
+            (Tx::new(10, 6, TxType::Chargeback, None), true),
+            (Tx::new(7, 6, TxType::Dispute, None), true),
+            (Tx::new(7, 6, TxType::Chargeback, None), true),
+        ];
+        for (tx, should_succeed) in txs {
+            let tx_id = tx.tx_id;
+            assert_eq!(should_succeed, ledger.process_tx(tx).await.is_ok(), "tx_id: {}", tx_id);
+        }
+
+        let exposure = |client, currency: &Currency, funds, held, negative, chargebacks, amount, locked| Exposure{
+            client,
+            currency: currency.clone(),
+            customer_funds: Amount::from(funds),
+            held: Amount::from(held),
+            negative_balances: Amount::from(negative),
+            chargebacks,
+            chargeback_amount: Amount::from(amount),
+            locked,
+        };
+        let default = Currency::default();
+        assert_eq!(ExposureReport{
+            totals: vec![
+                exposure(None, &default, 11_0000, 2_0000, -13_0000, 2, 15_0000, false),
+                exposure(None, &usd(), 6_0000, 0, 0, 0, 0, false),
+            ],
+            clients: vec![
+                exposure(Some(1), &default, 0, 0, -10_0000, 1, 11_0000, true),
+                exposure(Some(3), &usd(), 4_0000, 0, 0, 0, 0, true),
+                exposure(Some(7), &default, 0, 0, -3_0000, 1, 4_0000, true),
+                exposure(Some(7), &usd(), 2_0000, 0, 0, 0, 0, true),
+            ],
+            locked_accounts: 3,
+        }, ledger.exposure_report().await.unwrap());
+    }
+
+    // The books balance and the liability of every client is its total.
+    async fn assert_books_mirror_accounts(booking_repo: &InMemoryBookingRepository, accounts: &[AccountSummary]) {
+        let liabilities: HashMap<(u16, Currency), Amount> = booking_repo.trial_balance().await.unwrap().into_iter()
+            .filter_map(|r| r.client.map(|c| ((c, r.currency), r.balance)))
+            .collect();
+        for a in accounts {
+            let liability = liabilities.get(&(a.client, a.currency.clone())).copied().unwrap_or_default();
+            assert_eq!(a.total, (-liability).unwrap(), "client: {} {}", a.client, a.currency);
+        }
+    }
+
+    fn summary_sort(a: &AccountSummary, b: &AccountSummary) -> Ordering {
+        (a.client, &a.currency).cmp(&(b.client, &b.currency))
+    }
+
+    fn summary(client: u16, available: i64, held: i64, total: i64, locked: bool) -> AccountSummary {
+        AccountSummary{
+            client,
+            currency: Currency::default(),
+            available: available.into(),
+            held: held.into(),
+            authorized: 0.into(),
+            pending: 0.into(),
+            total: total.into(),
+            locked,
+            fees: 0.into(),
+            credit_limit: 0.into(),
+            headroom: available.into(),
+            status: if locked { AccountStatus::ChargebackLocked } else { AccountStatus::Active },
+        }
+    }
+
+    fn usd() -> Currency {
+        Currency::new("USD")
+    }
+
+    fn eur() -> Currency {
+        Currency::new("EUR")
     }
 }
\ No newline at end of file